@@ -0,0 +1,60 @@
+// Fan-out of received messages to several subscriptions through the dispatcher, by way of LocalBroker.
+// "Vec" gives every consumer its own copy of the payload as before shared buffers, "Payload" shares one buffer.
+// Both copy the payload once on receipt, as the paho callback does.
+//
+//     cargo bench --bench payload
+
+extern crate mqtt;
+
+use std::hint::black_box;
+use std::time::Instant;
+use mqtt::async::{LocalBroker, MessageBuilder, Payload, Qos, Subscription, TopicFilter, TopicName};
+
+
+const ROUNDS: usize = 1000;
+
+fn report(name: &str, consumers: usize, size: usize, start: Instant) {
+    let elapsed = start.elapsed();
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    let delivered = (ROUNDS * consumers) as f64;
+    println!("{:>8} {:>3} consumers {:>8} bytes: {:>12.0} deliveries/s {:>10.1} MB/s",
+             name, consumers, size, delivered / secs, delivered * size as f64 / secs / 1e6);
+}
+
+// Publishes ROUNDS messages and drains every subscription after each one, copy decides what a consumer keeps.
+fn run<F>(broker: &mut LocalBroker, subscriptions: &[Subscription], topic: &TopicName, data: &[u8], copy: F) where F: Fn(&Payload) -> usize {
+    let mut kept = 0;
+    for _ in 0..ROUNDS {
+        let msg = MessageBuilder::new(topic.clone()).payload(data).qos(Qos::AtLeastOnce).build();
+        broker.publish(&msg).unwrap();
+        for subscription in subscriptions.iter() {
+            for msg in subscription.messages(Some(0)) {
+                kept += copy(&msg.payload);
+            }
+        }
+    }
+    assert_eq!(kept, ROUNDS * subscriptions.len() * data.len());
+}
+
+fn main() {
+    let topic = TopicName::new("bench/payload").unwrap();
+    for &consumers in [1, 8, 32].iter() {
+        let mut broker = LocalBroker::new();
+        let subscriptions: Vec<Subscription> = (0..consumers).map(|i| {
+            let filter = if i % 2 == 0 { "bench/#" } else { "bench/+" };
+            broker.subscribe(&TopicFilter::new(filter).unwrap(), Qos::AtLeastOnce).unwrap()
+        }).collect();
+
+        for &size in [16, 1024, 64 * 1024, 1024 * 1024].iter() {
+            let data = vec![0xA5u8; size];
+
+            let start = Instant::now();
+            run(&mut broker, &subscriptions, &topic, &data, |payload| black_box(payload.to_vec()).len());
+            report("Vec", consumers, size, start);
+
+            let start = Instant::now();
+            run(&mut broker, &subscriptions, &topic, &data, |payload| black_box(payload.clone()).len());
+            report("Payload", consumers, size, start);
+        }
+    }
+}
@@ -4,7 +4,7 @@ extern crate fern;
 extern crate time;
 extern crate mqtt;
 
-use mqtt::async::{PersistenceType, Qos, MqttError, AsyncClient, AsyncConnectOptions, AsyncDisconnectOptions};
+use mqtt::async::{PersistenceType, Qos, MqttError, AsyncClient, AsyncConnectOptions, AsyncDisconnectOptions, TopicFilter};
 use std::error::Error;
 
 
@@ -28,7 +28,7 @@ fn setup_mqtt(server_address: &str, topic: &str, client_id: &str) -> Result<Asyn
     let connect_options = AsyncConnectOptions::new();
     let mut client = try!(AsyncClient::new(server_address, client_id, PersistenceType::Nothing, None));
     try!(client.connect(&connect_options));
-    try!(client.subscribe(topic, Qos::FireAndForget));
+    try!(client.subscribe(&try!(TopicFilter::new(topic)), Qos::FireAndForget));
     Ok(client)
 }
 
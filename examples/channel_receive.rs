@@ -4,7 +4,7 @@ extern crate fern;
 extern crate time;
 extern crate mqtt;
 
-use mqtt::async::{PersistenceType, Qos, MqttError, AsyncClient, AsyncConnectOptions, AsyncDisconnectOptions, Message};
+use mqtt::async::{PersistenceType, Qos, MqttError, AsyncClient, AsyncConnectOptions, AsyncDisconnectOptions, TopicFilter, Message};
 use std::error::Error;
 use std::sync::mpsc;
 
@@ -28,7 +28,7 @@ fn setup_mqtt(server_address: &str, topic: &str, client_id: &str, channel: mpsc:
     let connect_options = AsyncConnectOptions::new();
     let mut client = try!(AsyncClient::new(server_address, client_id, PersistenceType::Nothing, Some(channel)));
     try!(client.connect(&connect_options));
-    try!(client.subscribe(topic, Qos::FireAndForget));
+    try!(client.subscribe(&try!(TopicFilter::new(topic)), Qos::FireAndForget));
     Ok(client)
 }
 
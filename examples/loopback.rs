@@ -6,7 +6,7 @@ extern crate mqtt;
 
 use std::thread;
 use std::char;
-use mqtt::async::{PersistenceType, Qos, MqttError, AsyncClient, AsyncConnectOptions, AsyncDisconnectOptions};
+use mqtt::async::{PersistenceType, Qos, MqttError, AsyncClient, AsyncConnectOptions, AsyncDisconnectOptions, TopicName, TopicFilter};
 use std::error::Error;
 
 
@@ -30,7 +30,7 @@ fn setup_mqtt(server_address: &str, topic: &str, client_id: &str) -> Result<Asyn
     let connect_options = AsyncConnectOptions::new();
     let mut client = try!(AsyncClient::new(server_address, client_id, PersistenceType::Nothing, None));
     try!(client.connect(&connect_options));
-    try!(client.subscribe(topic, Qos::FireAndForget));
+    try!(client.subscribe(&try!(TopicFilter::new(topic)), Qos::FireAndForget));
     Ok(client)
 }
 
@@ -45,10 +45,11 @@ fn main() {
     let topic = "TestTopic";
     match setup_mqtt("tcp://localhost:1883", &topic, "TestClientId") {
         Ok(mut client) => {
+            let topic_name = TopicName::new(topic).unwrap();
             for i in 0..10 {
                 info!("send data len: {}", i);
                 data.push(char::from_digit(i % 10, 10).unwrap() as u8);
-                client.send(&data, &topic, Qos::FireAndForget, false).unwrap();
+                client.send(&data, &topic_name, Qos::FireAndForget, false).unwrap();
                 for message in client.messages(Some(100)) {
                     info!("{:?}", message);
                 }
@@ -0,0 +1,256 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use ffiasync;
+use libc::c_void;
+use std::ffi::CString;
+use std::mem;
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::{Duration, Instant};
+
+use super::Message;
+use super::client::c_message;
+use super::error::{MqttError, CommandError};
+use super::topic::TopicName;
+
+
+struct State {
+    results   : Vec<Option<Result<(), MqttError>>>,
+    in_flight : usize,
+    finished  : bool,
+}
+
+struct Batch {
+    state : Mutex<State>,
+    cvar  : Condvar,
+}
+
+impl Batch {
+    // Callbacks arriving after the batch timed out are ignored, their messages were already failed.
+    fn complete(&self, index: usize, result: Result<(), MqttError>) {
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            return
+        }
+        state.results[index] = Some(result);
+        state.in_flight -= 1;
+        self.cvar.notify_all();
+    }
+}
+
+// Handed to paho as callback context of one publish, reclaimed by whichever callback fires.
+struct SendContext {
+    batch : Arc<Batch>,
+    index : usize,
+}
+
+extern "C" fn send_succeeded(context: *mut c_void, _response: *mut ffiasync::MQTTAsync_successData) -> () {
+    assert!(!context.is_null());
+    let context: Box<SendContext> = unsafe {Box::from_raw(context as *mut SendContext)};
+    context.batch.complete(context.index, Ok(()));
+}
+
+extern "C" fn send_failed(context: *mut c_void, response: *mut ffiasync::MQTTAsync_failureData) -> () {
+    assert!(!context.is_null());
+    let context: Box<SendContext> = unsafe {Box::from_raw(context as *mut SendContext)};
+    let error = if response.is_null() {
+        CommandError::CallbackNullPtr
+    } else {
+        let resp: &mut ffiasync::MQTTAsync_failureData = unsafe {mem::transmute(response)};
+        CommandError::CallbackResponse(resp.code)
+    };
+    context.batch.complete(context.index, Err(MqttError::Send(error)));
+}
+
+fn start_publish(handle: ffiasync::MQTTAsync, batch: &Arc<Batch>, index: usize, topic: &TopicName, msg: &Message) {
+    let context = Box::into_raw(Box::new(SendContext {
+        batch : batch.clone(),
+        index : index,
+    }));
+    let mut responseoption = ffiasync::MQTTAsync_responseOptions {
+        struct_id       : ['M' as i8, 'Q' as i8, 'T' as i8, 'R' as i8],
+        struct_version  : 0,
+        onSuccess       : Some(send_succeeded),
+        onFailure       : Some(send_failed),
+        context         : context as *mut c_void,
+        token           : 0,
+    };
+    let mut message = c_message(&msg.payload, msg.qos, msg.retained);
+    let c_topic = CString::new(topic.as_str()).unwrap();  // validated, no null characters
+
+    // paho copies topic and payload, they need not outlive the call
+    let error = unsafe {
+        ffiasync::MQTTAsync_sendMessage(handle, c_topic.as_ptr(), &mut message, &mut responseoption)
+    };
+    if error != 0 {
+        // no callback will fire, take the context back
+        let context: Box<SendContext> = unsafe {Box::from_raw(context)};
+        context.batch.complete(index, Err(MqttError::Send(CommandError::ReturnCode(error))));
+    }
+}
+
+// Publishes messages in order keeping at most max_in_flight of them unacknowledged.
+// Returns when every publish has completed or failed, results are in the order of messages.
+// Messages not completed within timeout, including ones not yet started, fail with CommandError::Timeout.
+pub fn send_many(handle: ffiasync::MQTTAsync, messages: &[Message], max_in_flight: usize, timeout: Duration) -> Vec<Result<(), MqttError>> {
+    run(messages, max_in_flight, timeout, |batch, index, topic, msg| start_publish(handle, batch, index, topic, msg))
+}
+
+// start must eventually call Batch::complete for the message it is given.
+fn run<F>(messages: &[Message], max_in_flight: usize, timeout: Duration, mut start: F) -> Vec<Result<(), MqttError>>
+    where F: FnMut(&Arc<Batch>, usize, &TopicName, &Message)
+{
+    let max_in_flight = if max_in_flight == 0 { 1 } else { max_in_flight };
+    let deadline = Instant::now() + timeout;
+    let batch = Arc::new(Batch {
+        state: Mutex::new(State {
+            results   : messages.iter().map(|_| None).collect(),
+            in_flight : 0,
+            finished  : false,
+        }),
+        cvar: Condvar::new(),
+    });
+
+    for (index, msg) in messages.iter().enumerate() {
+        let topic = match TopicName::new(msg.topic.as_str()) {
+            Ok(topic) => topic,
+            Err(e)    => {
+                batch.state.lock().unwrap().results[index] = Some(Err(MqttError::Topic(e)));
+                continue
+            }
+        };
+
+        {
+            let mut state = batch.state.lock().unwrap();
+            while state.in_flight >= max_in_flight {
+                let now = Instant::now();
+                if now >= deadline {
+                    return finish(&mut state)
+                }
+                state = batch.cvar.wait_timeout(state, deadline - now).unwrap().0;
+            }
+            state.in_flight += 1;
+        }
+
+        start(&batch, index, &topic, msg);
+    }
+
+    let mut state = batch.state.lock().unwrap();
+    while state.in_flight > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            break
+        }
+        state = batch.cvar.wait_timeout(state, deadline - now).unwrap().0;
+    }
+    finish(&mut state)
+}
+
+fn finish(state: &mut State) -> Vec<Result<(), MqttError>> {
+    state.finished = true;
+    state.results.iter_mut()
+        .map(|result| result.take().unwrap_or(Err(MqttError::Send(CommandError::Timeout))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::max;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use super::{Batch, run};
+    use super::super::Message;
+    use super::super::error::{MqttError, CommandError};
+    use super::super::topic::TopicName;
+
+    // topic is not validated, so send_many can be given invalid ones
+    fn message(topic: &str) -> Message {
+        let mut msg = Message::builder(TopicName::new("batch").unwrap()).build();
+        msg.topic = topic.to_string();
+        msg
+    }
+
+    fn complete_later(batch: &Arc<Batch>, index: usize) {
+        let batch = batch.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(5));
+            batch.complete(index, Ok(()));
+        });
+    }
+
+    #[test]
+    fn invalid_topic_fails_only_that_message() {
+        let messages = vec![message("a"), message("a/+"), message("b")];
+        let mut started = Vec::new();
+        let results = run(&messages, 2, Duration::from_secs(5), |batch, index, _, _| {
+            started.push(index);
+            batch.complete(index, Ok(()));
+        });
+        assert_eq!(started, vec![0, 2]);
+        assert!(results[0].is_ok());
+        match results[1] {
+            Err(MqttError::Topic(_)) => {},
+            ref other                => panic!("unexpected {:?}", other),
+        }
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn zero_max_in_flight_sends_one_at_a_time() {
+        let messages = vec![message("a"), message("b"), message("c")];
+        let mut max_seen = 0;
+        let results = run(&messages, 0, Duration::from_secs(5), |batch, index, _, _| {
+            max_seen = max(max_seen, batch.state.lock().unwrap().in_flight);
+            complete_later(batch, index);
+        });
+        assert_eq!(max_seen, 1);
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn outstanding_messages_fail_after_timeout() {
+        let messages = vec![message("a"), message("b"), message("c")];
+        let mut pending = Vec::new();
+        let results = run(&messages, 2, Duration::from_millis(20), |batch, index, _, _| {
+            if index == 0 {
+                batch.complete(index, Ok(()));
+            } else {
+                // never acknowledged, like a publish kept by paho for a later session
+                pending.push((batch.clone(), index));
+            }
+        });
+        assert!(results[0].is_ok());
+        for result in &results[1..] {
+            match *result {
+                Err(MqttError::Send(CommandError::Timeout)) => {},
+                ref other                                    => panic!("unexpected {:?}", other),
+            }
+        }
+        // late acknowledgement after the batch gave up is ignored
+        for (batch, index) in pending {
+            batch.complete(index, Ok(()));
+        }
+    }
+}
@@ -0,0 +1,97 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use super::Message;
+use super::error::TopicError;
+use super::options::Qos;
+use super::payload::Payload;
+use super::topic::TopicName;
+
+
+// Builds outbound messages for AsyncClient::publish, defaults are empty payload, qos 0 and not retained.
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    topic    : TopicName,
+    payload  : Payload,
+    qos      : Qos,
+    retained : bool,
+}
+
+impl MessageBuilder {
+    pub fn new(topic: TopicName) -> Self {
+        MessageBuilder {
+            topic    : topic,
+            payload  : Payload::new(),
+            qos      : Qos::FireAndForget,
+            retained : false,
+        }
+    }
+
+    pub fn topic(mut self, topic: TopicName) -> Self {
+        self.topic = topic;
+        self
+    }
+
+    pub fn payload<P: Into<Payload>>(mut self, payload: P) -> Self {
+        self.payload = payload.into();
+        self
+    }
+
+    pub fn qos(mut self, qos: Qos) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    pub fn retained(mut self, retained: bool) -> Self {
+        self.retained = retained;
+        self
+    }
+
+    pub fn build(self) -> Message {
+        Message {
+            topic     : self.topic.as_str().to_string(),
+            payload   : self.payload,
+            qos       : self.qos,
+            retained  : self.retained,
+            duplicate : false,
+            msgid     : 0,
+            delivery  : None,
+        }
+    }
+}
+
+impl Message {
+    pub fn builder(topic: TopicName) -> MessageBuilder {
+        MessageBuilder::new(topic)
+    }
+
+    // Starts from this message, so received messages can be republished with some changes.
+    pub fn to_builder(&self) -> Result<MessageBuilder, TopicError> {
+        let topic = try!(TopicName::new(self.topic.as_str()));
+        Ok(MessageBuilder::new(topic)
+            .payload(self.payload.clone())
+            .qos(self.qos)
+            .retained(self.retained))
+    }
+}
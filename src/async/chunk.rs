@@ -0,0 +1,256 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::{MqttError, ChunkError};
+use super::iterator::AsyncClientIntoIterator;
+use super::options::Qos;
+use super::payload::Payload;
+use super::topic::TopicName;
+use super::wire::{put_u32, put_u64, get_u32, get_u64};
+
+
+// Chunk layout:
+//   "MQC" | version | transfer id (u64 BE) | sequence (u32 BE) | chunk count (u32 BE) | CRC-32 of whole payload (u32 BE) | data
+const MAGIC: &'static [u8] = b"MQC";
+const VERSION: u8 = 1;
+pub const CHUNK_HEADER_LEN: usize = 3 + 1 + 8 + 4 + 4 + 4;
+
+pub const MAX_TRANSFER_SIZE: usize = 16 * 1024 * 1024;
+pub const MAX_PENDING_TRANSFERS: usize = 64;
+
+// CRC-32 (IEEE 802.3), same as zlib and Ethernet.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+pub use super::wire::new_id as new_transfer_id;
+
+// Splits data into chunks carrying at most chunk_size bytes of data each. Empty data gives one empty chunk.
+pub fn split(data: &[u8], chunk_size: usize, transfer_id: u64) -> Result<Vec<Vec<u8>>, ChunkError> {
+    if chunk_size == 0 {
+        return Err(ChunkError::ChunkSize)
+    }
+    let pieces: Vec<&[u8]> = if data.is_empty() { vec![data] } else { data.chunks(chunk_size).collect() };
+    let count = pieces.len() as u32;
+    let checksum = crc32(data);
+
+    Ok(pieces.into_iter().enumerate().map(|(seq, piece)| {
+        let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + piece.len());
+        chunk.extend_from_slice(MAGIC);
+        chunk.push(VERSION);
+        put_u64(&mut chunk, transfer_id);
+        put_u32(&mut chunk, seq as u32);
+        put_u32(&mut chunk, count);
+        put_u32(&mut chunk, checksum);
+        chunk.extend_from_slice(piece);
+        chunk
+    }).collect())
+}
+
+pub fn is_chunk(data: &[u8]) -> bool {
+    data.len() >= CHUNK_HEADER_LEN && data.starts_with(MAGIC) && data[3] == VERSION
+}
+
+impl AsyncClient {
+    // Sends data as sequenced chunks of at most chunk_size data bytes, returns transfer id.
+    pub fn send_chunked<D: AsRef<[u8]>>(&mut self, data: D, topic: &TopicName, qos: Qos, chunk_size: usize) -> Result<u64, MqttError> {
+        let transfer_id = new_transfer_id();
+        for chunk in try!(split(data.as_ref(), chunk_size, transfer_id)) {
+            try!(self.send(chunk, topic, qos, false));
+        }
+        Ok(transfer_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub topic   : String,
+    pub id      : u64,
+    pub payload : Payload,
+}
+
+struct Partial {
+    count    : u32,
+    checksum : u32,
+    chunks   : HashMap<u32, Payload>,
+    size     : usize,
+    started  : Instant,
+}
+
+// Collects chunks per topic and transfer id, in any order and with duplicates, until a transfer is complete.
+// Transfers that are not complete within timeout are dropped by expire().
+// Transfers larger than max_size are dropped and at most max_pending incomplete transfers are kept,
+// so a sender can not make the receiver hold arbitrary amounts of data.
+pub struct Reassembler {
+    pub timeout     : Duration,
+    pub max_size    : usize,
+    pub max_pending : usize,
+    partial         : HashMap<(String, u64), Partial>,
+}
+
+impl Reassembler {
+    pub fn new(timeout: Duration) -> Self {
+        Reassembler {
+            timeout     : timeout,
+            max_size    : MAX_TRANSFER_SIZE,
+            max_pending : MAX_PENDING_TRANSFERS,
+            partial     : HashMap::new(),
+        }
+    }
+
+    // Returns the whole transfer when its last missing chunk arrives.
+    pub fn feed(&mut self, msg: &Message) -> Result<Option<Transfer>, ChunkError> {
+        let data: &[u8] = &msg.payload;
+        if !is_chunk(data) {
+            return Err(ChunkError::NotChunk)
+        }
+        let id       = get_u64(&data[4..12]);
+        let seq      = get_u32(&data[12..16]);
+        let count    = get_u32(&data[16..20]);
+        let checksum = get_u32(&data[20..24]);
+        if count == 0 || seq >= count {
+            return Err(ChunkError::Malformed)
+        }
+        // only a transfer of empty data has an empty chunk
+        if count as usize > self.max_size.max(1) {
+            return Err(ChunkError::TooLarge(id))
+        }
+
+        let key = (msg.topic.clone(), id);
+        if !self.partial.contains_key(&key) && self.partial.len() >= self.max_pending {
+            return Err(ChunkError::TooManyTransfers(self.max_pending))
+        }
+        let body = &data[CHUNK_HEADER_LEN..];
+        let complete = {
+            let partial = self.partial.entry(key.clone()).or_insert_with(|| Partial {
+                count    : count,
+                checksum : checksum,
+                chunks   : HashMap::new(),
+                size     : 0,
+                started  : Instant::now(),
+            });
+            if partial.count != count || partial.checksum != checksum {
+                return Err(ChunkError::Inconsistent(id))
+            }
+            // duplicates are ignored
+            if !partial.chunks.contains_key(&seq) {
+                partial.size += body.len();
+                partial.chunks.insert(seq, Payload::from(body));
+            }
+            if partial.size > self.max_size {
+                None
+            } else {
+                Some(partial.chunks.len() as u32 == partial.count)
+            }
+        };
+        let complete = match complete {
+            Some(complete) => complete,
+            None => {
+                self.partial.remove(&key);
+                return Err(ChunkError::TooLarge(id))
+            }
+        };
+        if !complete {
+            return Ok(None)
+        }
+
+        let partial = self.partial.remove(&key).unwrap();
+        let mut payload = Vec::new();
+        for seq in 0..partial.count {
+            payload.extend_from_slice(&partial.chunks[&seq]);
+        }
+        if crc32(&payload) != partial.checksum {
+            return Err(ChunkError::Checksum(id))
+        }
+        Ok(Some(Transfer {
+            topic   : key.0,
+            id      : id,
+            payload : Payload::from(payload),
+        }))
+    }
+
+    // Drops incomplete transfers older than timeout and returns their topics and ids.
+    pub fn expire(&mut self) -> Vec<(String, u64)> {
+        let timeout = self.timeout;
+        let expired: Vec<(String, u64)> = self.partial.iter()
+            .filter(|&(_, partial)| partial.started.elapsed() > timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired.iter() {
+            warn!("chunked transfer {} on {} timed out", key.1, key.0);
+            self.partial.remove(key);
+        }
+        expired
+    }
+
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+
+    // Yields complete transfers from received messages, other messages are skipped.
+    pub fn transfers<'a>(&'a mut self, messages: AsyncClientIntoIterator) -> Transfers<'a> {
+        Transfers {
+            reassembler : self,
+            messages    : messages,
+        }
+    }
+}
+
+pub struct Transfers<'a> {
+    reassembler : &'a mut Reassembler,
+    messages    : AsyncClientIntoIterator,
+}
+
+impl<'a> Iterator for Transfers<'a> {
+    type Item = Transfer;
+
+    fn next(&mut self) -> Option<Transfer> {
+        loop {
+            let msg = match self.messages.next() {
+                Some(msg) => msg,
+                None      => return None,
+            };
+            self.reassembler.expire();
+            match self.reassembler.feed(&msg) {
+                Ok(Some(transfer))      => return Some(transfer),
+                Ok(None)                => {},
+                Err(ChunkError::NotChunk) => debug!("skipping message that is not a chunk on {}", msg.topic),
+                Err(e)                  => warn!("dropping chunk on {}: {}", msg.topic, e),
+            }
+        }
+    }
+}
@@ -24,15 +24,26 @@
 
 use ffiasync;
 use libc::{c_char, c_int, c_void};
+use std::collections::BTreeMap;
 use std::ffi::{CStr, CString};
 use std::mem;
+use std::ptr;
 use std::slice;
-use std::sync::{Barrier, Arc, Mutex, Condvar};
+use std::sync::{Barrier, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
-use super::Message;
+use super::{Message, Delivery};
 use super::options::{PersistenceType, Qos, AsyncConnectOptions, AsyncDisconnectOptions};
-use super::error::{MqttError, CommandError, ConnectError, ConnectErrReturnCode, DisconnectError, DisconnectErrReturnCode, CallbackError};
+use super::error::{MqttError, PayloadError, CommandError, ConnectError, ConnectErrReturnCode, DisconnectError, DisconnectErrReturnCode, CallbackError};
+use super::batch;
 use super::iterator::AsyncClientIntoIterator;
+use super::dispatch::{self, Dispatcher, MessageQueue, SubscriberId};
+use super::subscription::{Subscription, RawHandle, SharedHandle};
+use super::topic::{TopicName, TopicFilter};
+use super::payload::Payload;
+use super::router::{Router, RouteId};
+use super::ratelimit::RateLimiter;
+use super::offline::OfflineQueue;
 
 use std::sync::mpsc;
 
@@ -48,8 +59,13 @@ impl AsyncClient {
         try!(ac.inner.create());
         Ok(ac)
     }
+    // Messages held in the offline queue are sent once connected.
     pub fn connect(&mut self, options: &AsyncConnectOptions) -> Result<(), MqttError> {
-        self.inner.connect(options)
+        try!(self.inner.connect(options));
+        if let Err(e) = self.flush_offline() {
+            warn!("sending offline queue after connect failed: {}", e);
+        }
+        Ok(())
     }
     pub fn disconnect(&mut self, options: &AsyncDisconnectOptions) -> Result<(), MqttError> {
         self.inner.disconnect(options)
@@ -57,29 +73,256 @@ impl AsyncClient {
     pub fn is_connected(&self) -> bool {
         self.inner.is_connected()
     }
-    pub fn send(&mut self, data: &[u8], topic: &str, qos: Qos, retained: bool) -> Result<(), MqttError> {
-        self.inner.send(data, topic, qos, retained)
+    // Accepts borrowed or owned bytes, including Payload, without copying them.
+    // Goes through the rate limiter if one is set. With an offline queue set, messages sent while disconnected
+    // are queued and messages queued earlier go out first.
+    pub fn send<D: AsRef<[u8]>>(&mut self, data: D, topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
+        self.send_or_queue(data, topic, qos, retained).map(|_| ())
+    }
+    // Same as send but returns the offline queue id if the message was queued,
+    // so it can be matched with OfflineEvent::Delivered and OfflineEvent::Dropped later.
+    pub fn send_or_queue<D: AsRef<[u8]>>(&mut self, data: D, topic: &TopicName, qos: Qos, retained: bool) -> Result<Option<u64>, MqttError> {
+        let data = data.as_ref();
+        if let Some(queue) = self.inner.offline.clone() {
+            if !self.is_connected() || (!queue.is_empty() && self.flush_offline().is_err()) {
+                return queue.push(data, topic, qos, retained).map(Some)
+            }
+            return match self.send_now(data, topic, qos, retained) {
+                Err(_) if !self.is_connected() => queue.push(data, topic, qos, retained).map(Some),
+                result                         => result.map(|_| None),
+            }
+        }
+        self.send_now(data, topic, qos, retained).map(|_| None)
+    }
+    fn send_now(&mut self, data: &[u8], topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
+        match self.inner.rate_limiter.clone() {
+            Some(limiter) => {
+                let inner = &mut self.inner;
+                limiter.send_with(data, topic, qos, retained, |data, topic, qos, retained| inner.send(data, topic, qos, retained))
+            }
+            None => self.inner.send(data, topic, qos, retained),
+        }
+    }
+    // Offline queue keeps messages the limiter can't send yet, only reports what was actually published.
+    fn send_unqueued(&mut self, data: &[u8], topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
+        match self.inner.rate_limiter.clone() {
+            Some(limiter) => {
+                let inner = &mut self.inner;
+                limiter.send_unqueued_with(data, topic, qos, retained, |data, topic, qos, retained| inner.send(data, topic, qos, retained))
+            }
+            None => self.inner.send(data, topic, qos, retained),
+        }
+    }
+    // Sends topic, payload, qos and retained flag of the message, received messages can be forwarded unchanged.
+    pub fn publish(&mut self, message: &Message) -> Result<(), MqttError> {
+        let topic = try!(TopicName::new(message.topic.as_str()));
+        self.send(&message.payload, &topic, message.qos, message.retained)
+    }
+    // With RatePolicy::Queue, messages held back stay queued until the next send or flush_rate_limited.
+    pub fn set_rate_limiter(&mut self, limiter: Option<RateLimiter>) {
+        self.inner.rate_limiter = limiter;
+    }
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.inner.rate_limiter.as_ref()
     }
-    pub fn subscribe(&mut self, topic: &str, qos: Qos) -> Result<(), MqttError> {
-        self.inner.subscribe(topic, qos)
+    pub fn set_offline_queue(&mut self, queue: Option<OfflineQueue>) {
+        self.inner.offline = queue;
     }
+    pub fn offline_queue(&self) -> Option<&OfflineQueue> {
+        self.inner.offline.as_ref()
+    }
+    // Sends messages held in the offline queue, stops at the first transient failure and keeps the failed message queued.
+    pub fn flush_offline(&mut self) -> Result<usize, MqttError> {
+        match self.inner.offline.clone() {
+            Some(queue) => queue.flush_with(|msg| {
+                let topic = try!(TopicName::new(msg.topic.as_str()));
+                self.send_unqueued(&msg.payload, &topic, msg.qos, msg.retained)
+            }),
+            None => Ok(0),
+        }
+    }
+    // Sends messages held back by a queueing rate limiter, waiting for tokens if wait is true.
+    pub fn flush_rate_limited(&mut self, wait: bool) -> Result<usize, MqttError> {
+        match self.inner.rate_limiter.clone() {
+            Some(limiter) => {
+                let inner = &mut self.inner;
+                limiter.flush_with(wait, |data, topic, qos, retained| inner.send(data, topic, qos, retained))
+            }
+            None => Ok(0),
+        }
+    }
+    // Publishes messages keeping up to max_in_flight of them outstanding instead of waiting for each one,
+    // returns result of every message in the same order. Paho maxInflight connect option caps the window as well.
+    // Messages still outstanding after timeout fail with CommandError::Timeout. Rate limiter is not applied.
+    pub fn send_many(&mut self, messages: &[Message], max_in_flight: usize, timeout: Duration) -> Vec<Result<(), MqttError>> {
+        batch::send_many(self.inner.handle, messages, max_in_flight, timeout)
+    }
+    pub fn subscribe(&mut self, topic: &TopicFilter, qos: Qos) -> Result<Subscription, MqttError> {
+        // start collecting before subscribing, retained messages may arrive right away
+        let queue = dispatch::new_queue();
+        let id = self.inner.dispatcher.add_filtered_queue(topic, queue.clone());
+        let subscription = Subscription::new(topic.clone(), queue, id, self.inner.dispatcher.clone(), self.inner.shared_handle.clone());
+        try!(self.inner.subscribe(topic, qos));
+        Ok(subscription)
+    }
+    pub fn unsubscribe(&mut self, topic: &TopicFilter) -> Result<(), MqttError> {
+        self.inner.unsubscribe(topic)
+    }
+    // Collects retained messages under the filter until first non-retained message arrives
+    // or nothing arrives for quiet_ms. A filter that was not subscribed before is unsubscribed afterwards,
+    // unless other handles use it by then.
+    pub fn retained_snapshot(&mut self, topic: &TopicFilter, qos: Qos, quiet_ms: u32) -> Result<BTreeMap<String, Message>, MqttError> {
+        let subscribed = self.inner.dispatcher.is_subscribed(topic);
+        let mut subscription = try!(self.subscribe(topic, qos));
+        subscription.set_unsubscribe_on_drop(!subscribed);
+        Ok(subscription.retained(quiet_ms))
+    }
+    // Deletes retained messages under the filter by publishing empty retained messages to them.
+    // Returns topics that were cleared.
+    pub fn clear_retained(&mut self, topic: &TopicFilter, qos: Qos, quiet_ms: u32) -> Result<Vec<String>, MqttError> {
+        let snapshot = try!(self.retained_snapshot(topic, qos, quiet_ms));
+        let mut cleared = Vec::new();
+        for (name, _) in snapshot.into_iter() {
+            let topic_name = try!(TopicName::new(name));
+            try!(self.send(&[][..], &topic_name, qos, true));
+            cleared.push(topic_name.as_str().to_string());
+        }
+        Ok(cleared)
+    }
+    // Messages matching a live Subscription handle go to that handle only.
     pub fn messages(&mut self, timeout_ms: Option<u32>) -> AsyncClientIntoIterator {
+        // client created with a channel starts queueing for the iterator only from now on
+        if self.inner.messages_id.is_none() {
+            self.inner.messages_id = Some(self.inner.dispatcher.add_fallback_queue(self.inner.messages.clone()));
+        }
         AsyncClientIntoIterator::new(self.inner.messages.clone(), timeout_ms)
     }
+    pub fn add_channel(&mut self, channel: mpsc::Sender<Message>) -> SubscriberId {
+        self.inner.dispatcher.add_channel(channel)
+    }
+    // Iterator is detached once it is dropped, remove_subscriber detaches it earlier.
+    pub fn add_iterator(&mut self, timeout_ms: Option<u32>) -> (SubscriberId, AsyncClientIntoIterator) {
+        let queue = dispatch::new_queue();
+        let id = self.inner.dispatcher.add_queue(queue.clone());
+        (id, AsyncClientIntoIterator::new(queue, timeout_ms))
+    }
+    pub fn add_callback<F>(&mut self, callback: F) -> SubscriberId where F: Fn(&Message) + Send + 'static {
+        self.inner.dispatcher.add_callback(callback)
+    }
+    // Messages pass through stages before reaching any consumer, a stage may rewrite or drop them.
+    pub fn add_stage<F>(&mut self, stage: F) where F: FnMut(Message) -> Option<Message> + Send + 'static {
+        self.inner.dispatcher.add_stage(stage)
+    }
+    pub fn add_router(&mut self, router: &Router) -> SubscriberId {
+        let router = router.clone();
+        self.inner.dispatcher.add_callback(move |msg| { router.dispatch(msg); })
+    }
+    // Subscribes to the filter and adds handler to the router, router must be attached with add_router.
+    // The filter stays subscribed when subscription handles on it are dropped.
+    pub fn route<F>(&mut self, router: &Router, topic: &TopicFilter, qos: Qos, handler: F) -> Result<RouteId, MqttError> where F: Fn(&Message) + Send + 'static {
+        // route first, retained messages may arrive right after subscribing
+        let id = router.add(topic, handler);
+        if let Err(e) = self.inner.subscribe(topic, qos) {
+            router.remove(id);
+            return Err(e)
+        }
+        self.inner.dispatcher.retain_filter(topic);
+        Ok(id)
+    }
+    pub fn remove_subscriber(&mut self, id: SubscriberId) -> bool {
+        if self.inner.messages_id == Some(id) {
+            self.inner.messages_id = None;
+        }
+        self.inner.dispatcher.remove(id)
+    }
+}
+
+
+// Paho message pointing at data, which must outlive the send call.
+// Zero-length payload is sent with null pointer, that is how retained messages are cleared.
+pub fn c_message(data: &[u8], qos: Qos, retained: bool) -> ffiasync::MQTTAsync_message {
+    let mut message = ffiasync::MQTTAsync_message {
+        struct_id       : ['M' as i8, 'Q' as i8, 'T' as i8, 'M' as i8],
+        struct_version  : 0,
+        payloadlen      : data.len() as i32,
+        payload         : ptr::null_mut(),
+        qos             : qos as c_int,
+        retained        : retained as c_int,
+        dup             : 0,
+        msgid           : 0,
+    };
+    if !data.is_empty() {
+        message.payload = data.as_ptr() as *mut c_void;
+    }
+    message
 }
 
+// Copies a message received from paho, subscriptions are the filters its topic matched.
+fn received_message(topic: String, c_message: &ffiasync::MQTTAsync_message, client_id: &str, subscriptions: Vec<String>) -> Message {
+    let delivery = Delivery {
+        client_id     : client_id.to_string(),
+        subscriptions : subscriptions,
+        instant       : Instant::now(),
+        time          : SystemTime::now(),
+    };
+
+    let payload = match c_message.payloadlen {
+        0  => Payload::new(),
+        _ => {
+            let payload_slice: &[u8] = unsafe {
+                    slice::from_raw_parts(c_message.payload as *mut u8, c_message.payloadlen as usize)
+            };
+            Payload::from(payload_slice)
+        }
+    };
+
+    let qos = match Qos::from_int(c_message.qos) {
+        Ok(qos) => qos,
+        Err(e)  => {
+            error!("received message with {}, delivering as qos 0", e);
+            Qos::FireAndForget
+        }
+    };
+
+    let retained: bool = match c_message.retained {
+        0 => false,
+        1 => true,
+        _ => unreachable!(),
+    };
+
+    let duplicate: bool = match c_message.dup {
+        0 => false,
+        1 => true,
+        _ => unreachable!(),
+    };
+
+    Message {
+        topic     : topic,
+        payload   : payload,
+        qos       : qos,
+        retained  : retained,
+        duplicate : duplicate,
+        msgid     : c_message.msgid,
+        delivery  : Some(delivery),
+    }
+}
 
 struct ImmovableClient {
     c_url               : CString,
     c_clientid          : CString,
+    clientid            : String,
     handle              : ffiasync::MQTTAsync,
+    shared_handle       : SharedHandle,
     persistence_context : c_void,
     persistence         : PersistenceType,
 
-    barrier       : Barrier,
-    action_result : Option<Result<(), CallbackError>>,
-    pub messages  : Arc<(Mutex<Vec<Message>>, Condvar)>,
-    channel       : Option<mpsc::Sender<Message>>,
+    barrier         : Barrier,
+    action_result   : Option<Result<(), CallbackError>>,
+    dispatcher      : Arc<Dispatcher>,
+    pub messages    : MessageQueue,
+    pub messages_id : Option<SubscriberId>,
+    rate_limiter    : Option<RateLimiter>,
+    offline         : Option<OfflineQueue>,
 }
 impl ImmovableClient {
     fn context(&mut self) -> *mut c_void {
@@ -87,17 +330,35 @@ impl ImmovableClient {
     }
 
     pub fn new(address: &str, clientid: &str, persistence: PersistenceType, message_channel: Option<mpsc::Sender<Message>>) -> Self {
+        let dispatcher = Arc::new(Dispatcher::new());
+        let messages = dispatch::new_queue();
+
+        // without a channel messages are queued for the iterator as before,
+        // except those taken by subscription handles so that nobody has to drain them twice
+        let messages_id = match message_channel {
+            Some(channel) => {
+                dispatcher.add_channel(channel);
+                None
+            }
+            None => Some(dispatcher.add_fallback_queue(messages.clone())),
+        };
+
         ImmovableClient {
                     c_url               : CString::new(address).unwrap(),
                     c_clientid          : CString::new(clientid).unwrap(),
+                    clientid            : clientid.to_string(),
                     handle              : unsafe{mem::zeroed()},
+                    shared_handle       : Arc::new(Mutex::new(None)),
                     persistence_context : unsafe{mem::zeroed()},
                     persistence         : persistence,
 
                     barrier         : Barrier::new(2),
                     action_result   : None,
-                    messages        : Arc::new((Mutex::new(Vec::new()), Condvar::new())),
-                    channel         : message_channel
+                    dispatcher      : dispatcher,
+                    messages        : messages,
+                    messages_id     : messages_id,
+                    rate_limiter    : None,
+                    offline         : None,
         }
     }
 
@@ -112,7 +373,10 @@ impl ImmovableClient {
                                        &mut self.persistence_context)
         };
         match error {
-            0   => { Ok(())},
+            0   => {
+                *self.shared_handle.lock().unwrap() = Some(RawHandle(self.handle));
+                Ok(())
+            },
             err => Err(MqttError::Create(err))
         }
     }
@@ -136,6 +400,22 @@ impl ImmovableClient {
         async_opts.connectTimeout    = options.connect_timeout;
         async_opts.retryInterval     = options.retry_interval;
 
+        // will strings must stay alive until connect returns
+        let mut _will_strings = None;
+        let mut will_opts: ffiasync::MQTTAsync_willOptions = Default::default();
+        if let Some(ref will) = options.will {
+            let c_topic   = CString::new(will.topic.as_str()).unwrap();  // validated, no null characters
+            let c_payload = try!(CString::new(will.payload.as_str()).map_err(|_| PayloadError::Encode("will payload contains null character".to_string())));
+            will_opts.struct_id      = ['M' as i8, 'Q' as i8, 'T' as i8, 'W' as i8];
+            will_opts.struct_version = 0;
+            will_opts.topicName      = c_topic.as_ptr();
+            will_opts.message        = c_payload.as_ptr();
+            will_opts.retained       = will.retained as c_int;
+            will_opts.qos            = will.qos as c_int;
+            async_opts.will          = &mut will_opts;
+            _will_strings = Some((c_topic, c_payload));
+        }
+
         // register callbacks
         async_opts.context   = self.context();
         async_opts.onSuccess = Some(Self::action_succeeded);
@@ -208,7 +488,7 @@ impl ImmovableClient {
         }
     }
 
-    pub fn send(&mut self, data: &[u8], topic: &str, qos: Qos, retained: bool) -> Result<(), MqttError> {
+    pub fn send(&mut self, data: &[u8], topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
         debug!("send..");
         let mut responseoption = ffiasync::MQTTAsync_responseOptions {
             struct_id       : ['M' as i8, 'Q' as i8, 'T' as i8, 'R' as i8],
@@ -219,23 +499,9 @@ impl ImmovableClient {
             token           : 0,
         };
 
-        let retained = match retained {
-            true => 1,
-            false => 0,
-        };
-
-        let mut message = ffiasync::MQTTAsync_message {
-            struct_id       : ['M' as i8, 'Q' as i8, 'T' as i8, 'M' as i8],
-            struct_version  : 0,
-            payloadlen      : data.len() as i32,
-            payload         : unsafe {mem::transmute::<&u8, *mut c_void>(&data[0])},
-            qos             : qos as c_int,
-            retained        : retained as c_int,
-            dup             : 0,
-            msgid           : 0,
-        };
+        let mut message = c_message(data, qos, retained);
 
-        let c_topic        = CString::new(topic).unwrap();
+        let c_topic        = CString::new(topic.as_str()).unwrap();  // validated, no null characters
         let array_topic    = c_topic.as_bytes_with_nul();
         self.action_result = None;
 
@@ -257,7 +523,7 @@ impl ImmovableClient {
         } else { Err(MqttError::Send(CommandError::ReturnCode(error))) }
     }
 
-    pub fn subscribe(&mut self, topic: &str, qos: Qos) -> Result<(), MqttError> {
+    pub fn subscribe(&mut self, topic: &TopicFilter, qos: Qos) -> Result<(), MqttError> {
         debug!("subscribe..");
         let mut responseoption = ffiasync::MQTTAsync_responseOptions {
             struct_id       : ['M' as i8, 'Q' as i8, 'T' as i8, 'R' as i8],
@@ -268,7 +534,7 @@ impl ImmovableClient {
             token           : 0,
         };
 
-        let c_topic     = CString::new(topic).unwrap();
+        let c_topic     = CString::new(topic.as_str()).unwrap();
         let array_topic = c_topic.as_bytes_with_nul();
 
         let c_qos: i32 = match qos {
@@ -278,6 +544,10 @@ impl ImmovableClient {
         };
         self.action_result = None;
 
+        // retained messages may be delivered before the barrier releases us
+        let was_subscribed = self.dispatcher.is_subscribed(topic);
+        self.dispatcher.subscribed(topic);
+
         let error = unsafe {
             ffiasync::MQTTAsync_subscribe(self.handle,
                                           mem::transmute::<&u8, *const c_char>(&array_topic[0]),
@@ -285,7 +555,7 @@ impl ImmovableClient {
                                           &mut responseoption)
         };
 
-        if error == 0 {
+        let result = if error == 0 {
             self.barrier.wait();
             match (self.is_connected(), &self.action_result) {
                 (true,  _                                     ) => Ok(()),
@@ -294,7 +564,48 @@ impl ImmovableClient {
                 (false, &Some(Err(CallbackError::Response(r)))) => Err(MqttError::Subscribe(CommandError::CallbackResponse(r))),
                 (false, &Some(Err(CallbackError::NullPtr))    ) => Err(MqttError::Subscribe(CommandError::CallbackNullPtr)),
             }
-        } else { Err(MqttError::Subscribe(CommandError::ReturnCode(error))) }
+        } else { Err(MqttError::Subscribe(CommandError::ReturnCode(error))) };
+
+        if result.is_err() && !was_subscribed {
+            self.dispatcher.unsubscribed(topic);
+        }
+        result
+    }
+
+    pub fn unsubscribe(&mut self, topic: &TopicFilter) -> Result<(), MqttError> {
+        debug!("unsubscribe..");
+        let mut responseoption = ffiasync::MQTTAsync_responseOptions {
+            struct_id       : ['M' as i8, 'Q' as i8, 'T' as i8, 'R' as i8],
+            struct_version  : 0,
+            onSuccess       : Some(Self::action_succeeded),
+            onFailure       : Some(Self::action_failed),
+            context         : self.context(),
+            token           : 0,
+        };
+
+        let c_topic     = CString::new(topic.as_str()).unwrap();
+        let array_topic = c_topic.as_bytes_with_nul();
+        self.action_result = None;
+
+        let error = unsafe {
+            ffiasync::MQTTAsync_unsubscribe(self.handle,
+                                            mem::transmute::<&u8, *const c_char>(&array_topic[0]),
+                                            &mut responseoption)
+        };
+
+        if error == 0 {
+            self.barrier.wait();
+            match (self.is_connected(), &self.action_result) {
+                (true,  _                                     ) => {
+                    self.dispatcher.unsubscribed(topic);
+                    Ok(())
+                },
+                (false, &None                                 ) => unreachable!(),  // barrier should ensure we have something
+                (false, &Some(Ok(()))                         ) => unreachable!(),  // callback and is_connected() don't agree?
+                (false, &Some(Err(CallbackError::Response(r)))) => Err(MqttError::Unsubscribe(CommandError::CallbackResponse(r))),
+                (false, &Some(Err(CallbackError::NullPtr))    ) => Err(MqttError::Unsubscribe(CommandError::CallbackNullPtr)),
+            }
+        } else { Err(MqttError::Unsubscribe(CommandError::ReturnCode(error))) }
     }
 
     #[allow(unused_variables)]
@@ -327,53 +638,14 @@ impl ImmovableClient {
         assert!(!amessage.is_null());
         let transmessage: &mut ffiasync::MQTTAsync_message = unsafe {mem::transmute(amessage)};
 
-        let payload = match transmessage.payloadlen {
-            0  => None,
-            _ => {
-                let payload_slice: &[u8] = unsafe {
-                        slice::from_raw_parts(transmessage.payload as *mut u8, transmessage.payloadlen as usize)
-                };
-                Some(payload_slice.to_vec())
-            }
-        };
-
         assert!(!context.is_null());
         let selfclient : &mut ImmovableClient = unsafe {mem::transmute(context)};
 
-        let qos = Qos::from_int(transmessage.qos);
-
-        let retained: bool = match transmessage.retained {
-            0 => false,
-            1 => true,
-            _ => unreachable!(),
-        };
-
-        let duplicate: bool = match transmessage.dup {
-            0 => false,
-            1 => true,
-            _ => unreachable!(),
-        };
-
-        let msg = Message {
-            topic     : topic,
-            payload   : payload,
-            qos       : qos,
-            retained  : retained,
-            duplicate : duplicate,
-        };
+        let subscriptions = selfclient.dispatcher.matching_subscriptions(&topic);
+        let msg = received_message(topic, transmessage, &selfclient.clientid, subscriptions);
 
-        // send message to channel or to iterator
-        match selfclient.channel {
-            Some(ref channel) => {
-                channel.send(msg).unwrap();
-            }
-            None => {
-                let &(ref msglock, ref cvar) = &*selfclient.messages;
-                let mut messages = msglock.lock().unwrap();
-                messages.push(msg);
-                cvar.notify_one();
-            }
-        }
+        // every attached channel, iterator and callback gets its own copy
+        selfclient.dispatcher.dispatch(msg);
 
         let mut msg = amessage;
         unsafe{ffiasync::MQTTAsync_freeMessage(&mut msg)};
@@ -384,6 +656,62 @@ impl ImmovableClient {
 }
 impl Drop for ImmovableClient {
     fn drop(&mut self) {
+        // subscriptions that outlive the client must not touch the destroyed handle
+        let mut shared_handle = self.shared_handle.lock().unwrap();
+        *shared_handle = None;
         unsafe{ffiasync::MQTTAsync_destroy(&mut self.handle)};
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Instant, SystemTime};
+    use super::{c_message, received_message};
+    use super::super::options::Qos;
+
+    #[test]
+    fn received_message_fields() {
+        let data = b"21.5";
+        let mut raw = c_message(data, Qos::AtLeastOnce, true);
+        raw.msgid = 7;
+        raw.dup = 1;
+        let before = SystemTime::now();
+        let msg = received_message("sensors/t".to_string(), &raw, "client", vec!["sensors/#".to_string()]);
+
+        assert_eq!(msg.topic, "sensors/t");
+        assert_eq!(&msg.payload[..], data);
+        assert_eq!(msg.qos, Qos::AtLeastOnce);
+        assert!(msg.retained);
+        assert!(msg.duplicate);
+        assert_eq!(msg.msgid, 7);
+        let delivery = msg.delivery.unwrap();
+        assert_eq!(delivery.client_id, "client");
+        assert_eq!(delivery.subscriptions, vec!["sensors/#"]);
+        assert!(delivery.time >= before && delivery.time <= SystemTime::now());
+        assert!(delivery.instant <= Instant::now());
+
+        let empty = received_message("a".to_string(), &c_message(&[], Qos::FireAndForget, false), "client", Vec::new());
+        assert!(empty.payload.is_empty());
+        assert!(!empty.duplicate);
+    }
+
+    #[test]
+    fn empty_payload_is_null() {
+        let message = c_message(&[], Qos::AtLeastOnce, true);
+        assert!(message.payload.is_null());
+        assert_eq!(message.payloadlen, 0);
+        assert_eq!(message.qos, 1);
+        assert_eq!(message.retained, 1);
+    }
+
+    #[test]
+    fn payload_points_at_data() {
+        let data = b"21.5";
+        let message = c_message(data, Qos::FireAndForget, false);
+        assert_eq!(message.payload as *const u8, data.as_ptr());
+        assert_eq!(message.payloadlen, 4);
+        assert_eq!(message.qos, 0);
+        assert_eq!(message.retained, 0);
+    }
+}
@@ -0,0 +1,114 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::{MqttError, PayloadError};
+use super::options::Qos;
+use super::topic::TopicName;
+
+#[cfg(any(feature = "serde", feature = "cbor", feature = "msgpack"))]
+use serde::Serialize;
+#[cfg(any(feature = "serde", feature = "cbor", feature = "msgpack"))]
+use serde::de::DeserializeOwned;
+
+
+// Converts values of type T to payload bytes and back.
+// Implementations for JSON, CBOR, MessagePack and Protobuf are behind features of the same name.
+pub trait Codec<T> {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, PayloadError>;
+    fn decode(&self, data: &[u8]) -> Result<T, PayloadError>;
+}
+
+impl AsyncClient {
+    pub fn send_encoded<T, C: Codec<T>>(&mut self, codec: &C, value: &T, topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
+        let data = try!(codec.encode(value));
+        self.send(data, topic, qos, retained)
+    }
+}
+
+impl Message {
+    pub fn decode<T, C: Codec<T>>(&self, codec: &C) -> Result<T, PayloadError> {
+        codec.decode(&self.payload)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+pub struct Json;
+
+#[cfg(feature = "serde")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for Json {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, PayloadError> {
+        ::serde_json::to_vec(value).map_err(|e| PayloadError::Encode(e.to_string()))
+    }
+    fn decode(&self, data: &[u8]) -> Result<T, PayloadError> {
+        ::serde_json::from_slice(data).map_err(|e| PayloadError::Decode(e.to_string()))
+    }
+}
+
+
+#[cfg(feature = "cbor")]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for Cbor {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, PayloadError> {
+        let mut data = Vec::new();
+        try!(::ciborium::ser::into_writer(value, &mut data).map_err(|e| PayloadError::Encode(e.to_string())));
+        Ok(data)
+    }
+    fn decode(&self, data: &[u8]) -> Result<T, PayloadError> {
+        ::ciborium::de::from_reader(data).map_err(|e| PayloadError::Decode(e.to_string()))
+    }
+}
+
+
+#[cfg(feature = "msgpack")]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl<T: Serialize + DeserializeOwned> Codec<T> for MessagePack {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, PayloadError> {
+        // named fields keep the encoding readable by other MessagePack implementations
+        ::rmp_serde::to_vec_named(value).map_err(|e| PayloadError::Encode(e.to_string()))
+    }
+    fn decode(&self, data: &[u8]) -> Result<T, PayloadError> {
+        ::rmp_serde::from_slice(data).map_err(|e| PayloadError::Decode(e.to_string()))
+    }
+}
+
+
+#[cfg(feature = "protobuf")]
+pub struct Protobuf;
+
+#[cfg(feature = "protobuf")]
+impl<T: ::prost::Message + Default> Codec<T> for Protobuf {
+    fn encode(&self, value: &T) -> Result<Vec<u8>, PayloadError> {
+        Ok(value.encode_to_vec())
+    }
+    fn decode(&self, data: &[u8]) -> Result<T, PayloadError> {
+        T::decode(data).map_err(|e| PayloadError::Decode(e.to_string()))
+    }
+}
@@ -0,0 +1,193 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::io::Read;
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::{MqttError, PayloadError};
+use super::options::Qos;
+use super::payload::Payload;
+use super::topic::TopicName;
+
+
+// Compressed payloads start with this marker followed by algorithm byte, so compressed and
+// uncompressed publishers can share topics. Text and JSON payloads never start with a null byte.
+const MARKER: &'static [u8] = b"\0MQZ";
+const HEADER_LEN: usize = 5;
+
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Algorithm {
+    fn id(&self) -> u8 {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Algorithm::Gzip => 1,
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            #[cfg(feature = "gzip")]
+            1 => Some(Algorithm::Gzip),
+            #[cfg(feature = "zstd")]
+            2 => Some(Algorithm::Zstd),
+            _ => None,
+        }
+    }
+
+    fn compress(&self, data: &[u8], level: i32) -> Result<Vec<u8>, PayloadError> {
+        let mut out = MARKER.to_vec();
+        out.push(self.id());
+        match *self {
+            #[cfg(feature = "gzip")]
+            Algorithm::Gzip => {
+                use std::io::Write;
+                let mut encoder = ::flate2::write::GzEncoder::new(out, ::flate2::Compression::new(level as u32));
+                try!(encoder.write_all(data).map_err(|e| PayloadError::Encode(e.to_string())));
+                encoder.finish().map_err(|e| PayloadError::Encode(e.to_string()))
+            }
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => {
+                let compressed = try!(::zstd::encode_all(data, level).map_err(|e| PayloadError::Encode(e.to_string())));
+                out.extend_from_slice(&compressed);
+                Ok(out)
+            }
+        }
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>, PayloadError> {
+        match *self {
+            #[cfg(feature = "gzip")]
+            Algorithm::Gzip => read_limited(::flate2::read::GzDecoder::new(data), max_size),
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => {
+                let decoder = try!(::zstd::stream::read::Decoder::new(data).map_err(|e| PayloadError::Decode(e.to_string())));
+                read_limited(decoder, max_size)
+            }
+        }
+    }
+}
+
+// Reads at most one byte past max_size, so a small malicious payload can't expand without bound.
+fn read_limited<R: Read>(reader: R, max_size: usize) -> Result<Vec<u8>, PayloadError> {
+    let mut out = Vec::new();
+    try!(reader.take(max_size as u64 + 1).read_to_end(&mut out).map_err(|e| PayloadError::Decode(e.to_string())));
+    if out.len() > max_size {
+        return Err(PayloadError::Decode(format!("decompressed payload exceeds {} bytes", max_size)))
+    }
+    Ok(out)
+}
+
+// Payloads shorter than threshold, and payloads that would not get smaller, are sent as they are.
+// Payloads that decompress to more than max_size bytes are rejected.
+pub struct Compression {
+    pub algorithm : Algorithm,
+    pub level     : i32,
+    pub threshold : usize,
+    pub max_size  : usize,
+}
+
+impl Compression {
+    pub fn new(algorithm: Algorithm) -> Self {
+        let level = match algorithm {
+            #[cfg(feature = "gzip")]
+            Algorithm::Gzip => 6,
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => 3,
+        };
+        Compression {
+            algorithm : algorithm,
+            level     : level,
+            threshold : 256,
+            max_size  : MAX_DECOMPRESSED_SIZE,
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Payload, PayloadError> {
+        if data.len() < self.threshold {
+            return Ok(Payload::from(data))
+        }
+        let compressed = try!(self.algorithm.compress(data, self.level));
+        if compressed.len() >= data.len() {
+            Ok(Payload::from(data))
+        } else {
+            Ok(Payload::from(compressed))
+        }
+    }
+
+    pub fn decompress(&self, payload: &Payload) -> Result<Payload, PayloadError> {
+        decompress(payload, self.max_size)
+    }
+}
+
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.len() >= HEADER_LEN && data.starts_with(MARKER)
+}
+
+// Uncompressed payload is returned without copying.
+pub fn decompress(payload: &Payload, max_size: usize) -> Result<Payload, PayloadError> {
+    if !is_compressed(payload) {
+        return Ok(payload.clone())
+    }
+    match Algorithm::from_id(payload[HEADER_LEN-1]) {
+        Some(algorithm) => Ok(Payload::from(try!(algorithm.decompress(&payload[HEADER_LEN..], max_size)))),
+        None => Err(PayloadError::Decode(format!("unsupported compression {}", payload[HEADER_LEN-1]))),
+    }
+}
+
+impl AsyncClient {
+    pub fn send_compressed<D: AsRef<[u8]>>(&mut self, compression: &Compression, data: D, topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
+        let payload = try!(compression.compress(data.as_ref()));
+        self.send(payload, topic, qos, retained)
+    }
+
+    // Decompresses payloads of all messages received from now on before they reach consumers.
+    // Messages that fail to decompress or exceed max_size are delivered unchanged.
+    pub fn decompress_received(&mut self, max_size: usize) {
+        self.add_stage(move |mut msg: Message| {
+            match decompress(&msg.payload, max_size) {
+                Ok(payload) => msg.payload = payload,
+                Err(e)      => warn!("decompressing message on {} failed: {}", msg.topic, e),
+            }
+            Some(msg)
+        });
+    }
+}
+
+impl Message {
+    pub fn decompressed(&self, max_size: usize) -> Result<Payload, PayloadError> {
+        decompress(&self.payload, max_size)
+    }
+}
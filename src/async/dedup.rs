@@ -0,0 +1,125 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::Message;
+use super::client::AsyncClient;
+use super::options::Qos;
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupStats {
+    pub passed     : u64,
+    pub suppressed : u64,
+}
+
+struct State {
+    window : Duration,
+    seen   : HashMap<(i32, u64), Instant>,
+    order  : VecDeque<((i32, u64), Instant)>,
+    stats  : DedupStats,
+}
+
+// Drops redelivered messages already seen within window. Messages are keyed by msgid and a hash of topic
+// and payload. Only messages with the dup flag set are suppressed, brokers reuse freed msgids right away
+// so a device repeating the same payload produces new messages with the same key.
+// QoS 0 messages are never redelivered and all share msgid 0, so they always pass.
+// Cheap handle, clones share state and counters.
+#[derive(Clone)]
+pub struct Deduplicator {
+    state: Arc<Mutex<State>>,
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration) -> Self {
+        Deduplicator {
+            state: Arc::new(Mutex::new(State {
+                window : window,
+                seen   : HashMap::new(),
+                order  : VecDeque::new(),
+                stats  : DedupStats::default(),
+            }))
+        }
+    }
+
+    fn key(msg: &Message) -> (i32, u64) {
+        let mut hasher = DefaultHasher::new();
+        msg.topic.hash(&mut hasher);
+        msg.payload.hash(&mut hasher);
+        (msg.msgid, hasher.finish())
+    }
+
+    // Returns false if message is a duplicate.
+    pub fn check(&self, msg: &Message) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if msg.qos == Qos::FireAndForget {
+            state.stats.passed += 1;
+            return true
+        }
+
+        let key = Self::key(msg);
+        let now = Instant::now();
+
+        while let Some(&(old, seen_at)) = state.order.front() {
+            if now.duration_since(seen_at) <= state.window {
+                break
+            }
+            state.order.pop_front();
+            // key may have been seen again since, keep it until its latest entry expires
+            if state.seen.get(&old) == Some(&seen_at) {
+                state.seen.remove(&old);
+            }
+        }
+
+        if msg.duplicate && state.seen.contains_key(&key) {
+            state.stats.suppressed += 1;
+            debug!("suppressed duplicate message {} on {}", msg.msgid, msg.topic);
+            false
+        } else {
+            state.seen.insert(key, now);
+            state.order.push_back((key, now));
+            state.stats.passed += 1;
+            true
+        }
+    }
+
+    pub fn stats(&self) -> DedupStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+impl AsyncClient {
+    // Drops duplicate deliveries before they reach any consumer. Returned handle gives access to counters.
+    pub fn deduplicate(&mut self, window: Duration) -> Deduplicator {
+        let dedup = Deduplicator::new(window);
+        let stage = dedup.clone();
+        self.add_stage(move |msg| if stage.check(&msg) { Some(msg) } else { None });
+        dedup
+    }
+}
@@ -0,0 +1,335 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Weak, Mutex, Condvar};
+use std::sync::mpsc;
+use std::collections::HashMap;
+use super::Message;
+use super::topic::{self, TopicTree};
+
+
+pub type SubscriberId = usize;
+pub type MessageQueue = Arc<(Mutex<Vec<Message>>, Condvar)>;
+
+pub fn new_queue() -> MessageQueue {
+    Arc::new((Mutex::new(Vec::new()), Condvar::new()))
+}
+
+// Queues are held weakly, whoever reads a queue keeps it alive.
+enum Consumer {
+    Channel(mpsc::Sender<Message>),
+    Queue(Weak<(Mutex<Vec<Message>>, Condvar)>),
+    Fallback(Weak<(Mutex<Vec<Message>>, Condvar)>),
+    Callback(Box<dyn Fn(&Message) + Send>),
+}
+
+struct Entry {
+    id       : SubscriberId,
+    filter   : Option<String>,
+    consumer : Consumer,
+}
+
+struct Consumers {
+    next_id : SubscriberId,
+    list    : Vec<Entry>,
+}
+
+// Hands a copy of every received message to each attached consumer.
+// Consumers are called from the paho callback thread while the consumer list is locked,
+// therefore callbacks must not attach or detach consumers themselves.
+// Also keeps track of filters subscribed at the broker so messages can tell which ones matched,
+// and of how many subscription handles use each filter.
+// Before reaching consumers every message passes through stages in the order they were added,
+// a stage may change the message or drop it by returning None.
+pub struct Dispatcher {
+    consumers     : Mutex<Consumers>,
+    subscriptions : Mutex<TopicTree<String>>,
+    stages        : Mutex<Vec<Box<dyn FnMut(Message) -> Option<Message> + Send>>>,
+    handles       : Mutex<HashMap<String, usize>>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Dispatcher {
+            consumers: Mutex::new(Consumers {
+                next_id : 0,
+                list    : Vec::new(),
+            }),
+            subscriptions: Mutex::new(TopicTree::new()),
+            stages: Mutex::new(Vec::new()),
+            handles: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn add_stage<F>(&self, stage: F) where F: FnMut(Message) -> Option<Message> + Send + 'static {
+        self.stages.lock().unwrap().push(Box::new(stage));
+    }
+
+    pub fn subscribed(&self, filter: &str) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.remove(filter);
+        subscriptions.insert(filter, filter.to_string());
+    }
+
+    pub fn is_subscribed(&self, filter: &str) -> bool {
+        self.subscriptions.lock().unwrap().contains(filter)
+    }
+
+    pub fn unsubscribed(&self, filter: &str) {
+        self.subscriptions.lock().unwrap().remove(filter);
+    }
+
+    pub fn retain_filter(&self, filter: &str) {
+        *self.handles.lock().unwrap().entry(filter.to_string()).or_insert(0) += 1;
+    }
+
+    // Returns true when the last handle using the filter is released.
+    pub fn release_filter(&self, filter: &str) -> bool {
+        let mut handles = self.handles.lock().unwrap();
+        let last = match handles.get_mut(filter) {
+            Some(count) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => return true,
+        };
+        if last {
+            handles.remove(filter);
+        }
+        last
+    }
+
+    pub fn matching_subscriptions(&self, topic: &str) -> Vec<String> {
+        self.subscriptions.lock().unwrap().matches(topic).into_iter().cloned().collect()
+    }
+
+    fn add(&self, filter: Option<String>, consumer: Consumer) -> SubscriberId {
+        let mut consumers = self.consumers.lock().unwrap();
+        let id = consumers.next_id;
+        consumers.next_id += 1;
+        consumers.list.push(Entry {
+            id       : id,
+            filter   : filter,
+            consumer : consumer,
+        });
+        id
+    }
+
+    pub fn add_channel(&self, channel: mpsc::Sender<Message>) -> SubscriberId {
+        self.add(None, Consumer::Channel(channel))
+    }
+
+    pub fn add_queue(&self, queue: MessageQueue) -> SubscriberId {
+        self.add(None, Consumer::Queue(Arc::downgrade(&queue)))
+    }
+
+    pub fn add_callback<F>(&self, callback: F) -> SubscriberId where F: Fn(&Message) + Send + 'static {
+        self.add(None, Consumer::Callback(Box::new(callback)))
+    }
+
+    // queue gets only messages that no filtered consumer has taken
+    pub fn add_fallback_queue(&self, queue: MessageQueue) -> SubscriberId {
+        self.add(None, Consumer::Fallback(Arc::downgrade(&queue)))
+    }
+
+    // queue gets only messages whose topic matches the filter
+    pub fn add_filtered_queue(&self, filter: &str, queue: MessageQueue) -> SubscriberId {
+        self.add(Some(filter.to_string()), Consumer::Queue(Arc::downgrade(&queue)))
+    }
+
+    pub fn remove(&self, id: SubscriberId) -> bool {
+        let mut consumers = self.consumers.lock().unwrap();
+        let before = consumers.list.len();
+        consumers.list.retain(|entry| entry.id != id);
+        consumers.list.len() != before
+    }
+
+    pub fn dispatch(&self, msg: Message) {
+        // user code must not unwind into the paho callback thread or poison our locks
+        let mut msg = msg;
+        for stage in self.stages.lock().unwrap().iter_mut() {
+            msg = match panic::catch_unwind(AssertUnwindSafe(|| stage(msg))) {
+                Ok(Some(msg)) => msg,
+                Ok(None)      => return,
+                Err(_)        => {
+                    error!("message stage panicked, message dropped");
+                    return
+                }
+            };
+        }
+
+        let mut consumers = self.consumers.lock().unwrap();
+        let claimed = consumers.list.iter().any(|entry| entry.filter.as_ref().map_or(false, |filter| topic::topic_matches(filter, &msg.topic)));
+        // channels and queues whose reading end is gone are detached automatically
+        consumers.list.retain(|entry| {
+            if let Some(ref filter) = entry.filter {
+                if !topic::topic_matches(filter, &msg.topic) {
+                    return true
+                }
+            }
+            match entry.consumer {
+                Consumer::Channel(ref channel) => channel.send(msg.clone()).is_ok(),
+                Consumer::Fallback(ref queue) if claimed => queue.upgrade().is_some(),
+                Consumer::Queue(ref queue) | Consumer::Fallback(ref queue) => {
+                    let queue = match queue.upgrade() {
+                        Some(queue) => queue,
+                        None        => return false,
+                    };
+                    let &(ref msglock, ref cvar) = &*queue;
+                    let mut messages = msglock.lock().unwrap();
+                    messages.push(msg.clone());
+                    cvar.notify_one();
+                    true
+                }
+                Consumer::Callback(ref callback) => {
+                    if panic::catch_unwind(AssertUnwindSafe(|| callback(&msg))).is_err() {
+                        error!("message callback {} panicked on {}", entry.id, msg.topic);
+                    }
+                    true
+                }
+            }
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc;
+    use super::{Dispatcher, new_queue};
+    use super::super::Message;
+    use super::super::topic::TopicName;
+
+    fn message(topic: &str) -> Message {
+        Message::builder(TopicName::new(topic).unwrap()).payload("x").build()
+    }
+
+    fn queued(queue: &super::MessageQueue) -> Vec<String> {
+        queue.0.lock().unwrap().iter().map(|msg| msg.topic.clone()).collect()
+    }
+
+    #[test]
+    fn fan_out_to_every_consumer() {
+        let dispatcher = Dispatcher::new();
+        let (tx, rx) = mpsc::channel();
+        let queue = new_queue();
+        let filtered = new_queue();
+        let seen = Arc::new(Mutex::new(0));
+        let counter = seen.clone();
+        dispatcher.add_channel(tx);
+        dispatcher.add_queue(queue.clone());
+        dispatcher.add_filtered_queue("b/#", filtered.clone());
+        dispatcher.add_callback(move |_| *counter.lock().unwrap() += 1);
+
+        dispatcher.dispatch(message("a"));
+        dispatcher.dispatch(message("b/c"));
+
+        assert_eq!(rx.try_iter().map(|msg| msg.topic).collect::<Vec<_>>(), vec!["a", "b/c"]);
+        assert_eq!(queued(&queue), vec!["a", "b/c"]);
+        assert_eq!(queued(&filtered), vec!["b/c"]);
+        assert_eq!(*seen.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn fallback_skips_claimed_messages() {
+        let dispatcher = Dispatcher::new();
+        let fallback = new_queue();
+        let filtered = new_queue();
+        dispatcher.add_fallback_queue(fallback.clone());
+        dispatcher.add_filtered_queue("a/+", filtered.clone());
+        dispatcher.dispatch(message("a/b"));
+        dispatcher.dispatch(message("c"));
+        assert_eq!(queued(&fallback), vec!["c"]);
+        assert_eq!(queued(&filtered), vec!["a/b"]);
+    }
+
+    #[test]
+    fn filter_handles_are_counted() {
+        let dispatcher = Dispatcher::new();
+        dispatcher.retain_filter("a/#");
+        dispatcher.retain_filter("a/#");
+        assert!(!dispatcher.release_filter("a/#"));
+        assert!(dispatcher.release_filter("a/#"));
+        assert!(dispatcher.release_filter("b"));
+    }
+
+    #[test]
+    fn removed_consumer_gets_nothing() {
+        let dispatcher = Dispatcher::new();
+        let first = new_queue();
+        let second = new_queue();
+        let id = dispatcher.add_queue(first.clone());
+        dispatcher.add_queue(second.clone());
+        assert!(dispatcher.remove(id));
+        assert!(!dispatcher.remove(id));
+
+        dispatcher.dispatch(message("a"));
+        assert!(queued(&first).is_empty());
+        assert_eq!(queued(&second), vec!["a"]);
+    }
+
+    #[test]
+    fn dropped_channel_is_detached() {
+        let dispatcher = Dispatcher::new();
+        let (tx, rx) = mpsc::channel();
+        let id = dispatcher.add_channel(tx);
+        drop(rx);
+        dispatcher.dispatch(message("a"));
+        assert!(!dispatcher.remove(id));
+    }
+
+    #[test]
+    fn dropped_queue_is_detached() {
+        let dispatcher = Dispatcher::new();
+        let queue = new_queue();
+        let fallback = new_queue();
+        let id = dispatcher.add_queue(queue.clone());
+        let fallback_id = dispatcher.add_fallback_queue(fallback.clone());
+        dispatcher.dispatch(message("a"));
+        assert_eq!(queued(&queue), vec!["a"]);
+        drop(queue);
+        drop(fallback);
+        dispatcher.dispatch(message("b"));
+        assert!(!dispatcher.remove(id));
+        assert!(!dispatcher.remove(fallback_id));
+    }
+
+    #[test]
+    fn panicking_callback_and_stage_are_contained() {
+        let dispatcher = Dispatcher::new();
+        let queue = new_queue();
+        dispatcher.add_callback(|msg| if msg.topic == "boom" { panic!("callback") });
+        dispatcher.add_queue(queue.clone());
+        dispatcher.dispatch(message("boom"));
+        dispatcher.dispatch(message("a"));
+        assert_eq!(queued(&queue), vec!["boom", "a"]);
+
+        dispatcher.add_stage(|msg| if msg.topic == "stage" { panic!("stage") } else { Some(msg) });
+        dispatcher.dispatch(message("stage"));
+        dispatcher.dispatch(message("b"));
+        assert_eq!(queued(&queue), vec!["boom", "a", "b"]);
+    }
+}
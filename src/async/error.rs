@@ -23,6 +23,7 @@
  */
 
 use std::fmt;
+use std::time::Duration;
 use std::error::Error;
 
 
@@ -32,7 +33,15 @@ pub enum MqttError {
     Connect(ConnectError),
     Disconnect(DisconnectError),
     Subscribe(CommandError),
+    Unsubscribe(CommandError),
     Send(CommandError),
+    Topic(TopicError),
+    Payload(PayloadError),
+    Rpc(RpcError),
+    RateLimit(RateLimitError),
+    Offline(OfflineError),
+    Security(SecurityError),
+    Chunk(ChunkError),
 }
 impl fmt::Display for MqttError {
     fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
@@ -41,7 +50,15 @@ impl fmt::Display for MqttError {
             MqttError::Connect(ref x)   => fmt::Display::fmt(&format!("MqttError::Connect({:?})", x), f),
             MqttError::Disconnect(ref x)   => fmt::Display::fmt(&format!("MqttError::Disconnect({:?})", x), f),
             MqttError::Subscribe(ref x) => fmt::Display::fmt(&format!("MqttError::Subscribe({:?})", x), f),
+            MqttError::Unsubscribe(ref x) => fmt::Display::fmt(&format!("MqttError::Unsubscribe({:?})", x), f),
             MqttError::Send(ref x)      => fmt::Display::fmt(&format!("MqttError::Send({:?})", x), f),
+            MqttError::Topic(ref x)     => fmt::Display::fmt(&format!("MqttError::Topic({:?})", x), f),
+            MqttError::Payload(ref x)   => fmt::Display::fmt(&format!("MqttError::Payload({:?})", x), f),
+            MqttError::Rpc(ref x)       => fmt::Display::fmt(&format!("MqttError::Rpc({:?})", x), f),
+            MqttError::RateLimit(ref x) => fmt::Display::fmt(&format!("MqttError::RateLimit({:?})", x), f),
+            MqttError::Offline(ref x)   => fmt::Display::fmt(&format!("MqttError::Offline({:?})", x), f),
+            MqttError::Security(ref x)  => fmt::Display::fmt(&format!("MqttError::Security({:?})", x), f),
+            MqttError::Chunk(ref x)     => fmt::Display::fmt(&format!("MqttError::Chunk({:?})", x), f),
         }
     }
 }
@@ -52,16 +69,60 @@ impl Error for MqttError {
             MqttError::Connect(_)   => "Mqtt connect failed",
             MqttError::Disconnect(_)   => "Mqtt disconnect failed",
             MqttError::Subscribe(_) => "Mqtt subscribe failed",
+            MqttError::Unsubscribe(_) => "Mqtt unsubscribe failed",
             MqttError::Send(_)      => "Mqtt send failed",
+            MqttError::Topic(_)     => "Mqtt topic is not valid",
+            MqttError::Payload(_)   => "Mqtt payload could not be encoded",
+            MqttError::Rpc(_)       => "Mqtt request failed",
+            MqttError::RateLimit(_) => "Mqtt send rate limit exceeded",
+            MqttError::Offline(_)   => "Mqtt offline queue did not accept message",
+            MqttError::Security(_)  => "Mqtt message could not be sealed or verified",
+            MqttError::Chunk(_)     => "Mqtt payload could not be chunked",
         }
     }
 }
+impl From<TopicError> for MqttError {
+    fn from(err: TopicError) -> Self {
+        MqttError::Topic(err)
+    }
+}
+impl From<PayloadError> for MqttError {
+    fn from(err: PayloadError) -> Self {
+        MqttError::Payload(err)
+    }
+}
+impl From<RpcError> for MqttError {
+    fn from(err: RpcError) -> Self {
+        MqttError::Rpc(err)
+    }
+}
+impl From<RateLimitError> for MqttError {
+    fn from(err: RateLimitError) -> Self {
+        MqttError::RateLimit(err)
+    }
+}
+impl From<OfflineError> for MqttError {
+    fn from(err: OfflineError) -> Self {
+        MqttError::Offline(err)
+    }
+}
+impl From<ChunkError> for MqttError {
+    fn from(err: ChunkError) -> Self {
+        MqttError::Chunk(err)
+    }
+}
+impl From<SecurityError> for MqttError {
+    fn from(err: SecurityError) -> Self {
+        MqttError::Security(err)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum CommandError {
     ReturnCode(i32),
     CallbackResponse(i32),
-    CallbackNullPtr
+    CallbackNullPtr,
+    Timeout,
 }
 
 #[derive(Debug, Clone)]
@@ -115,8 +176,228 @@ impl DisconnectErrReturnCode {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QosError {
+    Invalid(i32),
+}
+impl fmt::Display for QosError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(&format!("QosError::{:?}", self), f)
+    }
+}
+impl Error for QosError {
+    fn description(&self) -> &str {
+        match *self {
+            QosError::Invalid(_) => "Qos must be 0, 1 or 2",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum CallbackError {
     Response(i32),
     NullPtr
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopicError {
+    Empty,
+    TooLong(usize),
+    NullCharacter,
+    WildcardInName,
+    InvalidWildcard,
+}
+impl fmt::Display for TopicError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            TopicError::TooLong(ref x) => fmt::Display::fmt(&format!("TopicError::TooLong({:?})", x), f),
+            ref x                      => fmt::Display::fmt(&format!("TopicError::{:?}", x), f),
+        }
+    }
+}
+impl Error for TopicError {
+    fn description(&self) -> &str {
+        match *self {
+            TopicError::Empty           => "Topic is empty",
+            TopicError::TooLong(_)      => "Topic is longer than 65535 bytes",
+            TopicError::NullCharacter   => "Topic contains null character",
+            TopicError::WildcardInName  => "Topic name contains wildcard character",
+            TopicError::InvalidWildcard => "Topic filter wildcard does not occupy whole level",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    InvalidTemplate(String),
+    DuplicateParam(String),
+    MissingParam(String),
+    InvalidValue(String, String),
+    NoMatch(String),
+    Param(String),
+    Topic(TopicError),
+}
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(&format!("TemplateError::{:?}", self), f)
+    }
+}
+impl Error for TemplateError {
+    fn description(&self) -> &str {
+        match *self {
+            TemplateError::InvalidTemplate(_) => "Topic template is not valid",
+            TemplateError::DuplicateParam(_)  => "Topic template parameter is used more than once",
+            TemplateError::MissingParam(_)    => "Topic template parameter is missing",
+            TemplateError::InvalidValue(_, _) => "Topic template parameter value is not valid",
+            TemplateError::NoMatch(_)         => "Topic does not match topic template",
+            TemplateError::Param(_)           => "Topic template parameter could not be converted",
+            TemplateError::Topic(_)           => "Topic template does not produce a valid topic",
+        }
+    }
+}
+impl From<TopicError> for TemplateError {
+    fn from(err: TopicError) -> Self {
+        TemplateError::Topic(err)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadError {
+    Encode(String),
+    Decode(String),
+}
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            PayloadError::Encode(ref x) => fmt::Display::fmt(&format!("PayloadError::Encode({})", x), f),
+            PayloadError::Decode(ref x) => fmt::Display::fmt(&format!("PayloadError::Decode({})", x), f),
+        }
+    }
+}
+impl Error for PayloadError {
+    fn description(&self) -> &str {
+        match *self {
+            PayloadError::Encode(_) => "Payload encoding failed",
+            PayloadError::Decode(_) => "Payload decoding failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkError {
+    NotChunk,
+    Malformed,
+    Inconsistent(u64),
+    Checksum(u64),
+    ChunkSize,
+    TooLarge(u64),
+    TooManyTransfers(usize),
+}
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(&format!("ChunkError::{:?}", self), f)
+    }
+}
+impl Error for ChunkError {
+    fn description(&self) -> &str {
+        match *self {
+            ChunkError::NotChunk            => "Payload is not a chunk",
+            ChunkError::Malformed           => "Chunk header is malformed",
+            ChunkError::Inconsistent(_)     => "Chunk header does not agree with earlier chunks of the transfer",
+            ChunkError::Checksum(_)         => "Reassembled payload checksum does not match",
+            ChunkError::ChunkSize           => "Chunk size must be greater than zero",
+            ChunkError::TooLarge(_)         => "Chunked transfer exceeds maximum size",
+            ChunkError::TooManyTransfers(_) => "Too many incomplete chunked transfers",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcError {
+    Timeout(u64),
+    Malformed,
+}
+impl fmt::Display for RpcError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(&format!("RpcError::{:?}", self), f)
+    }
+}
+impl Error for RpcError {
+    fn description(&self) -> &str {
+        match *self {
+            RpcError::Timeout(_) => "No response to request within timeout",
+            RpcError::Malformed  => "Request or response envelope is malformed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitError {
+    Exceeded(String, Duration),
+    QueueFull(String),
+    InvalidLimit,
+}
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(&format!("RateLimitError::{:?}", self), f)
+    }
+}
+impl Error for RateLimitError {
+    fn description(&self) -> &str {
+        match *self {
+            RateLimitError::Exceeded(_, _) => "Rate limit of topic exceeded",
+            RateLimitError::QueueFull(_)   => "Rate limited message queue is full",
+            RateLimitError::InvalidLimit   => "Limit rate must be a positive number",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OfflineError {
+    QueueFull(usize),
+}
+impl fmt::Display for OfflineError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(&format!("OfflineError::{:?}", self), f)
+    }
+}
+impl Error for OfflineError {
+    fn description(&self) -> &str {
+        match *self {
+            OfflineError::QueueFull(_) => "Offline queue is full",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecurityError {
+    NoKey(String),
+    CanNotSign(String),
+    Malformed,
+    BadSignature,
+    Decrypt,
+    Encrypt,
+    Stale(u64),
+    Replayed,
+    Random(String),
+}
+impl fmt::Display for SecurityError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(&format!("SecurityError::{:?}", self), f)
+    }
+}
+impl Error for SecurityError {
+    fn description(&self) -> &str {
+        match *self {
+            SecurityError::NoKey(_)      => "No key configured for topic",
+            SecurityError::CanNotSign(_) => "Topic key can only verify",
+            SecurityError::Malformed     => "Secured payload is malformed",
+            SecurityError::BadSignature  => "Signature does not match",
+            SecurityError::Decrypt       => "Payload decryption failed",
+            SecurityError::Encrypt       => "Payload encryption failed",
+            SecurityError::Stale(_)      => "Message timestamp is outside of allowed window",
+            SecurityError::Replayed      => "Message nonce was already seen",
+            SecurityError::Random(_)     => "Random nonce generation failed",
+        }
+    }
+}
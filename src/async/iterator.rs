@@ -23,17 +23,17 @@
  */
 
 use time;
-use std::sync::{Arc, Mutex, Condvar};
 use super::Message;
+use super::dispatch::MessageQueue;
 
 
 pub struct AsyncClientIntoIterator {
-    messages   : Arc<(Mutex<Vec<Message>>, Condvar)>,
+    messages   : MessageQueue,
     timeout_ms : Option<u32>,
 }
 
 impl AsyncClientIntoIterator {
-    pub fn new(messages: Arc<(Mutex<Vec<Message>>, Condvar)>, timeout_ms: Option<u32>) -> Self {
+    pub fn new(messages: MessageQueue, timeout_ms: Option<u32>) -> Self {
         AsyncClientIntoIterator{ messages   : messages,
                                  timeout_ms : timeout_ms
         }
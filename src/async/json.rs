@@ -0,0 +1,48 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::{MqttError, PayloadError};
+use super::options::Qos;
+use super::topic::TopicName;
+
+
+// Same encoding as the Json codec, without requiring the type to go both ways.
+impl AsyncClient {
+    pub fn send_json<T: Serialize + ?Sized>(&mut self, value: &T, topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
+        let data = try!(serde_json::to_vec(value).map_err(|e| PayloadError::Encode(e.to_string())));
+        self.send(data, topic, qos, retained)
+    }
+}
+
+impl Message {
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, PayloadError> {
+        serde_json::from_slice(&self.payload).map_err(|e| PayloadError::Decode(e.to_string()))
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::sync::{Arc, Mutex};
+
+use super::Message;
+use super::dispatch::{self, Dispatcher};
+use super::error::MqttError;
+use super::options::Qos;
+use super::subscription::Subscription;
+use super::topic::{TopicName, TopicFilter};
+
+
+// In-process stand-in for a broker, delivers published messages to matching subscriptions of all clones.
+// There is no retained message store, qos is passed through as published.
+// Useful for testing code written against Transport without a running broker.
+#[derive(Clone)]
+pub struct LocalBroker {
+    dispatcher : Arc<Dispatcher>,
+}
+
+impl LocalBroker {
+    pub fn new() -> Self {
+        LocalBroker {
+            dispatcher : Arc::new(Dispatcher::new()),
+        }
+    }
+
+    pub fn publish(&mut self, message: &Message) -> Result<(), MqttError> {
+        try!(TopicName::new(message.topic.as_str()));
+        let mut msg = message.clone();
+        msg.duplicate = false;
+        msg.delivery = None;
+        self.dispatcher.dispatch(msg);
+        Ok(())
+    }
+
+    pub fn subscribe(&mut self, topic: &TopicFilter, _qos: Qos) -> Result<Subscription, MqttError> {
+        let queue = dispatch::new_queue();
+        let id = self.dispatcher.add_filtered_queue(topic, queue.clone());
+        self.dispatcher.subscribed(topic);
+        Ok(Subscription::new(topic.clone(), queue, id, self.dispatcher.clone(), Arc::new(Mutex::new(None))))
+    }
+}
+
+impl Default for LocalBroker {
+    fn default() -> Self {
+        LocalBroker::new()
+    }
+}
@@ -22,22 +22,93 @@
  * SOFTWARE.
  */
 
+use std::time::{Instant, SystemTime};
+
+mod batch;
+mod builder;
+mod chunk;
 mod client;
+mod codec;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod compression;
+mod dedup;
+mod dispatch;
 mod error;
 mod iterator;
+#[cfg(feature = "serde")]
+mod json;
+mod local;
+mod monitor;
+mod namespace;
+mod offline;
 mod options;
+mod payload;
+mod presence;
+mod ratelimit;
+mod router;
+mod rpc;
+#[cfg(feature = "secure")]
+mod secure;
+#[cfg(feature = "serde")]
+mod shadow;
+mod subscription;
+mod template;
+mod topic;
+mod wire;
 
-pub use self::options::{PersistenceType, Qos, AsyncConnectOptions, AsyncDisconnectOptions};
-pub use self::error::{MqttError, CommandError, ConnectError, ConnectErrReturnCode, DisconnectError, DisconnectErrReturnCode};
+pub use self::options::{PersistenceType, Qos, Will, AsyncConnectOptions, AsyncDisconnectOptions};
+pub use self::error::{MqttError, CommandError, ConnectError, ConnectErrReturnCode, DisconnectError, DisconnectErrReturnCode, QosError, TopicError, TemplateError, PayloadError, ChunkError, RpcError, RateLimitError, OfflineError, SecurityError};
 pub use self::iterator::AsyncClientIntoIterator;
 pub use self::client::AsyncClient;
+pub use self::dispatch::SubscriberId;
+pub use self::dedup::{Deduplicator, DedupStats};
+pub use self::subscription::Subscription;
+pub use self::payload::Payload;
+pub use self::builder::MessageBuilder;
+pub use self::codec::Codec;
+pub use self::chunk::{Reassembler, Transfer, Transfers, split, is_chunk, crc32, new_transfer_id, CHUNK_HEADER_LEN, MAX_TRANSFER_SIZE, MAX_PENDING_TRANSFERS};
+#[cfg(feature = "serde")]
+pub use self::codec::Json;
+#[cfg(feature = "cbor")]
+pub use self::codec::Cbor;
+#[cfg(feature = "msgpack")]
+pub use self::codec::MessagePack;
+#[cfg(feature = "protobuf")]
+pub use self::codec::Protobuf;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use self::compression::{Algorithm, Compression, MAX_DECOMPRESSED_SIZE, decompress, is_compressed};
+#[cfg(feature = "secure")]
+pub use self::secure::{SecureLayer, TopicKeys, SignatureKey, SignatureKind, SigningKey, VerifyingKey, Verification, VerifiedMessage};
+pub use self::presence::{Presence, PresenceWatcher, PeerTracker, Peer, PeerStatus, PresenceChange};
+pub use self::offline::{OfflineQueue, OfflineEvent, OverflowPolicy, DropReason};
+pub use self::ratelimit::{RateLimiter, RatePolicy, Limit, RateStats};
+pub use self::router::{Router, RouteId};
+#[cfg(feature = "serde")]
+pub use self::shadow::{Shadow, ShadowDocument, ShadowEvent, merge_patch};
+pub use self::rpc::{Transport, Requester, Responder};
+pub use self::local::LocalBroker;
+pub use self::template::{TopicTemplate, FromTopicParams};
+pub use self::monitor::{BrokerMonitor, BrokerStats, StatChange};
+pub use self::namespace::{Namespace, NamespacedClient, NamespacedSubscription, NamespacedIntoIterator};
+pub use self::topic::{TopicName, TopicFilter, TopicTree, topic_matches, is_valid_topic, is_valid_filter};
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Message {
     pub topic     : String,
-    pub payload   : Option<Vec<u8>>,
+    pub payload   : Payload,
     pub qos       : Qos,
     pub retained  : bool,
     pub duplicate : bool,
+    pub msgid     : i32,
+    pub delivery  : Option<Delivery>,
+}
+
+// Filled in for messages received from the broker, None for messages created locally.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub client_id     : String,
+    pub subscriptions : Vec<String>,
+    pub instant       : Instant,
+    pub time          : SystemTime,
 }
@@ -0,0 +1,141 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::BTreeMap;
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::MqttError;
+use super::options::Qos;
+use super::subscription::Subscription;
+use super::topic::TopicFilter;
+
+
+pub const SYS_FILTER: &'static str = "$SYS/#";
+
+// Typed view of the most common $SYS statistics published by mosquitto and HiveMQ.
+// Every received value is also kept in `raw` under its full topic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BrokerStats {
+    pub version           : Option<String>,
+    pub uptime_secs       : Option<u64>,
+    pub clients_connected : Option<u64>,
+    pub messages_received : Option<u64>,
+    pub messages_sent     : Option<u64>,
+    pub bytes_received    : Option<u64>,
+    pub bytes_sent        : Option<u64>,
+    pub raw               : BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatChange {
+    pub topic    : String,
+    pub previous : Option<String>,
+    pub current  : String,
+}
+
+// "1234 seconds" and "1234" both give 1234
+fn parse_number(value: &str) -> Option<u64> {
+    value.split_whitespace().next().and_then(|n| n.parse().ok())
+}
+
+impl BrokerStats {
+    pub fn new() -> Self {
+        BrokerStats::default()
+    }
+
+    // Returns change if the value differs from the previously received one.
+    pub fn update(&mut self, topic: &str, value: &str) -> Option<StatChange> {
+        match topic {
+            "$SYS/broker/version"                 => self.version = Some(value.to_string()),
+            "$SYS/broker/uptime"                  => self.uptime_secs = parse_number(value),
+            "$SYS/broker/clients/connected" |
+            "$SYS/broker/clients/active"          => self.clients_connected = parse_number(value),
+            "$SYS/broker/messages/received" |
+            "$SYS/broker/messages/incoming/total" => self.messages_received = parse_number(value),
+            "$SYS/broker/messages/sent" |
+            "$SYS/broker/messages/outgoing/total" => self.messages_sent = parse_number(value),
+            "$SYS/broker/bytes/received" |
+            "$SYS/broker/bytes/incoming"          => self.bytes_received = parse_number(value),
+            "$SYS/broker/bytes/sent" |
+            "$SYS/broker/bytes/outgoing"          => self.bytes_sent = parse_number(value),
+            _ => {}
+        }
+
+        let previous = self.raw.insert(topic.to_string(), value.to_string());
+        if previous.as_ref().map(|p| &p[..]) == Some(value) {
+            None
+        } else {
+            Some(StatChange {
+                topic    : topic.to_string(),
+                previous : previous,
+                current  : value.to_string(),
+            })
+        }
+    }
+}
+
+// Subscribes to $SYS/# and keeps a snapshot of broker statistics.
+// Subscription is removed from the broker when monitor is dropped.
+pub struct BrokerMonitor {
+    subscription : Subscription,
+    stats        : BrokerStats,
+}
+
+impl BrokerMonitor {
+    pub fn new(client: &mut AsyncClient) -> Result<Self, MqttError> {
+        let filter = TopicFilter::new(SYS_FILTER).unwrap();
+        let mut subscription = try!(client.subscribe(&filter, Qos::FireAndForget));
+        subscription.set_unsubscribe_on_drop(true);
+        Ok(BrokerMonitor {
+            subscription : subscription,
+            stats        : BrokerStats::new(),
+        })
+    }
+
+    // Applies a single $SYS message, other messages are ignored.
+    pub fn apply(&mut self, msg: &Message) -> Option<StatChange> {
+        if !msg.topic.starts_with("$SYS/") {
+            return None
+        }
+        let value = String::from_utf8_lossy(&msg.payload).into_owned();
+        self.stats.update(&msg.topic, value.trim())
+    }
+
+    // Applies all messages received so far and waits for more until there is nothing new for timeout_ms.
+    // Returns changes in the order they were received.
+    pub fn poll(&mut self, timeout_ms: u32) -> Vec<StatChange> {
+        let mut changes = Vec::new();
+        for msg in self.subscription.messages(Some(timeout_ms)) {
+            if let Some(change) = self.apply(&msg) {
+                changes.push(change);
+            }
+        }
+        changes
+    }
+
+    pub fn stats(&self) -> &BrokerStats {
+        &self.stats
+    }
+}
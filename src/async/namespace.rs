@@ -0,0 +1,194 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::{MqttError, TopicError};
+use super::iterator::AsyncClientIntoIterator;
+use super::options::Qos;
+use super::subscription::Subscription;
+use super::topic::{TopicName, TopicFilter};
+
+
+// Topic prefix such as "tenant/<id>", trailing slashes are ignored.
+// Namespace itself can not contain wildcards, therefore relative filters only ever match inside of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    prefix : TopicName,
+}
+
+impl Namespace {
+    pub fn new(prefix: &str) -> Result<Self, TopicError> {
+        Ok(Namespace {
+            prefix : try!(TopicName::new(prefix.trim_end_matches('/'))),
+        })
+    }
+
+    pub fn prefix(&self) -> &TopicName {
+        &self.prefix
+    }
+
+    pub fn topic(&self, topic: &TopicName) -> Result<TopicName, TopicError> {
+        TopicName::new(format!("{}/{}", self.prefix, topic))
+    }
+
+    pub fn filter(&self, filter: &TopicFilter) -> Result<TopicFilter, TopicError> {
+        TopicFilter::new(format!("{}/{}", self.prefix, filter))
+    }
+
+    // Returns the message with topic relative to the namespace, None if it is outside of it.
+    // Relative "#" also matches the namespace itself, such message gets an empty topic.
+    pub fn strip(&self, mut msg: Message) -> Option<Message> {
+        let relative = match msg.topic.strip_prefix(self.prefix.as_str()) {
+            Some("")       => String::new(),
+            Some(relative) => match relative.strip_prefix('/') {
+                Some(relative) => relative.to_string(),
+                None           => return self.outside(msg),
+            },
+            None => return self.outside(msg),
+        };
+        msg.topic = relative;
+        Some(msg)
+    }
+
+    fn outside(&self, msg: Message) -> Option<Message> {
+        debug!("dropping message outside of namespace: {}", msg.topic);
+        None
+    }
+}
+
+// Prefixes every topic with a namespace and strips it from received messages,
+// so application code can use the same relative topics for every tenant.
+// Received messages outside of the namespace are dropped.
+pub struct NamespacedClient {
+    client    : AsyncClient,
+    namespace : Namespace,
+}
+
+impl NamespacedClient {
+    pub fn new(client: AsyncClient, prefix: &str) -> Result<Self, TopicError> {
+        Ok(NamespacedClient {
+            client    : client,
+            namespace : try!(Namespace::new(prefix)),
+        })
+    }
+
+    pub fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    pub fn prefix(&self) -> &TopicName {
+        self.namespace.prefix()
+    }
+
+    pub fn topic(&self, topic: &TopicName) -> Result<TopicName, TopicError> {
+        self.namespace.topic(topic)
+    }
+
+    pub fn filter(&self, filter: &TopicFilter) -> Result<TopicFilter, TopicError> {
+        self.namespace.filter(filter)
+    }
+
+    pub fn strip(&self, msg: Message) -> Option<Message> {
+        self.namespace.strip(msg)
+    }
+
+    pub fn send<D: AsRef<[u8]>>(&mut self, data: D, topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
+        let topic = try!(self.topic(topic));
+        self.client.send(data, &topic, qos, retained)
+    }
+
+    pub fn subscribe(&mut self, filter: &TopicFilter, qos: Qos) -> Result<NamespacedSubscription, MqttError> {
+        let filter = try!(self.filter(filter));
+        let subscription = try!(self.client.subscribe(&filter, qos));
+        Ok(NamespacedSubscription {
+            subscription : subscription,
+            namespace    : self.namespace.clone(),
+        })
+    }
+
+    pub fn unsubscribe(&mut self, filter: &TopicFilter) -> Result<(), MqttError> {
+        let filter = try!(self.filter(filter));
+        self.client.unsubscribe(&filter)
+    }
+
+    pub fn messages(&mut self, timeout_ms: Option<u32>) -> NamespacedIntoIterator {
+        NamespacedIntoIterator {
+            inner     : self.client.messages(timeout_ms),
+            namespace : self.namespace.clone(),
+        }
+    }
+
+    pub fn inner(&self) -> &AsyncClient {
+        &self.client
+    }
+
+    pub fn inner_mut(&mut self) -> &mut AsyncClient {
+        &mut self.client
+    }
+
+    pub fn into_inner(self) -> AsyncClient {
+        self.client
+    }
+}
+
+pub struct NamespacedSubscription {
+    subscription : Subscription,
+    namespace    : Namespace,
+}
+
+impl NamespacedSubscription {
+    pub fn messages(&self, timeout_ms: Option<u32>) -> NamespacedIntoIterator {
+        NamespacedIntoIterator {
+            inner     : self.subscription.messages(timeout_ms),
+            namespace : self.namespace.clone(),
+        }
+    }
+
+    pub fn set_unsubscribe_on_drop(&mut self, unsubscribe: bool) {
+        self.subscription.set_unsubscribe_on_drop(unsubscribe);
+    }
+
+    pub fn inner(&self) -> &Subscription {
+        &self.subscription
+    }
+}
+
+pub struct NamespacedIntoIterator {
+    inner     : AsyncClientIntoIterator,
+    namespace : Namespace,
+}
+
+impl Iterator for NamespacedIntoIterator {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        loop {
+            match self.inner.next() {
+                Some(msg) => if let Some(msg) = self.namespace.strip(msg) { return Some(msg) },
+                None      => return None,
+            }
+        }
+    }
+}
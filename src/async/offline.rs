@@ -0,0 +1,227 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use super::Message;
+use super::builder::MessageBuilder;
+use super::error::{MqttError, CommandError, OfflineError};
+use super::options::Qos;
+use super::topic::TopicName;
+
+
+// What happens to a message sent to a full queue. Reject returns an error for the new message,
+// the other policies accept it and report the dropped one as an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    DropOldest,
+    DropNewest,
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    Overflow,
+    Cleared,
+    Failed,
+}
+
+// Outcome of a queued message, id is the one returned when it was queued.
+#[derive(Debug, Clone)]
+pub enum OfflineEvent {
+    Delivered(u64, Message),
+    Dropped(u64, Message, DropReason),
+}
+
+const MAX_EVENTS: usize = 1024;
+
+struct State {
+    capacity   : usize,
+    policy     : OverflowPolicy,
+    next_id    : u64,
+    queue      : VecDeque<(u64, Message)>,
+    events     : VecDeque<OfflineEvent>,
+    max_events : usize,
+}
+
+impl State {
+    // Oldest events are discarded once max_events are waiting to be taken.
+    fn event(&mut self, event: OfflineEvent) {
+        if self.max_events == 0 {
+            return
+        }
+        while self.events.len() >= self.max_events {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    // Removes message id from the front unless pushes with DropOldest already removed it.
+    fn remove_front(&mut self, id: u64) {
+        if self.queue.front().map(|&(front, _)| front) == Some(id) {
+            self.queue.pop_front();
+        }
+    }
+}
+
+// Paho return codes for messages it will never accept: bad UTF-8, null parameter, truncated topic,
+// bad structure and bad QoS.
+const REJECTED: [i32; 5] = [-5, -6, -7, -8, -9];
+
+// Whether sending may succeed later, such as after reconnecting. Invalid topics and payloads never do.
+fn is_transient(error: &MqttError) -> bool {
+    match *error {
+        MqttError::Topic(_) | MqttError::Payload(_)     => false,
+        MqttError::Send(CommandError::ReturnCode(code)) => !REJECTED.contains(&code),
+        _                                               => true,
+    }
+}
+
+// Holds messages sent while the client is disconnected and sends them in order after the next connect.
+// Cheap handle, clones share the queue and events.
+#[derive(Clone)]
+pub struct OfflineQueue {
+    state: Arc<Mutex<State>>,
+}
+
+impl OfflineQueue {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        OfflineQueue {
+            state: Arc::new(Mutex::new(State {
+                capacity   : capacity,
+                policy     : policy,
+                next_id    : 0,
+                queue      : VecDeque::new(),
+                events     : VecDeque::new(),
+                max_events : MAX_EVENTS,
+            }))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().queue.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.state.lock().unwrap().capacity
+    }
+
+    // Limits events kept until events() is called, 0 disables them. Defaults to 1024.
+    pub fn set_max_events(&self, max_events: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.max_events = max_events;
+        while state.events.len() > max_events {
+            state.events.pop_front();
+        }
+    }
+
+    // Queues a copy of the message and returns its id.
+    pub fn push(&self, data: &[u8], topic: &TopicName, qos: Qos, retained: bool) -> Result<u64, MqttError> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        let msg = MessageBuilder::new(topic.clone())
+            .payload(data)
+            .qos(qos)
+            .retained(retained)
+            .build();
+
+        if state.queue.len() >= state.capacity {
+            match state.policy {
+                OverflowPolicy::Reject => return Err(MqttError::Offline(OfflineError::QueueFull(state.capacity))),
+                OverflowPolicy::DropNewest => {
+                    state.next_id += 1;
+                    debug!("offline queue full, dropping message {} to {}", id, topic);
+                    state.event(OfflineEvent::Dropped(id, msg, DropReason::Overflow));
+                    return Ok(id)
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some((old_id, old)) = state.queue.pop_front() {
+                        debug!("offline queue full, dropping message {} to {}", old_id, old.topic);
+                        state.event(OfflineEvent::Dropped(old_id, old, DropReason::Overflow));
+                    } else {
+                        // zero capacity, nothing to make room in
+                        state.next_id += 1;
+                        state.event(OfflineEvent::Dropped(id, msg, DropReason::Overflow));
+                        return Ok(id)
+                    }
+                }
+            }
+        }
+        state.next_id += 1;
+        state.queue.push_back((id, msg));
+        Ok(id)
+    }
+
+    // Sends queued messages in order until the queue is empty or send fails with a transient error,
+    // in which case the failed message stays at the front and the error is returned.
+    // Messages that can never be sent are dropped and reported as DropReason::Failed.
+    pub fn flush_with<F>(&self, mut send: F) -> Result<usize, MqttError> where F: FnMut(&Message) -> Result<(), MqttError> {
+        let mut delivered = 0;
+        loop {
+            let (id, msg) = match self.state.lock().unwrap().queue.front() {
+                Some(&(id, ref msg)) => (id, msg.clone()),
+                None                 => return Ok(delivered),
+            };
+            let result = send(&msg);
+            let mut state = self.state.lock().unwrap();
+            match result {
+                Ok(()) => {
+                    state.remove_front(id);
+                    state.event(OfflineEvent::Delivered(id, msg));
+                    delivered += 1;
+                }
+                Err(e) => {
+                    if is_transient(&e) {
+                        return Err(e)
+                    }
+                    warn!("dropping queued message {} to {}: {}", id, msg.topic, e);
+                    state.remove_front(id);
+                    state.event(OfflineEvent::Dropped(id, msg, DropReason::Failed));
+                }
+            }
+        }
+    }
+
+    // Drops all queued messages, they are reported as cleared.
+    pub fn clear(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let cleared: Vec<(u64, Message)> = state.queue.drain(..).collect();
+        let count = cleared.len();
+        for (id, msg) in cleared {
+            state.event(OfflineEvent::Dropped(id, msg, DropReason::Cleared));
+        }
+        count
+    }
+
+    // Takes events that happened since last call.
+    pub fn events(&self) -> Vec<OfflineEvent> {
+        let mut state = self.state.lock().unwrap();
+        state.events.drain(..).collect()
+    }
+}
@@ -23,8 +23,12 @@
  */
 
 use ffiasync;
+use std::convert::TryFrom;
 use std::ptr;
 
+use super::error::QosError;
+use super::topic::TopicName;
+
 #[derive(Debug, Copy, Clone)]
 pub enum PersistenceType {
     Default = 0,
@@ -32,22 +36,28 @@ pub enum PersistenceType {
     User    = 2,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Qos {
     FireAndForget  = 0,
     AtLeastOnce    = 1,
     OnceAndOneOnly = 2,
 }
 impl Qos {
-    pub fn from_int(i:i32) -> Self {
+    pub fn from_int(i:i32) -> Result<Self, QosError> {
         match i {
-            0 => Qos::FireAndForget,
-            1 => Qos::AtLeastOnce,
-            2 => Qos::OnceAndOneOnly,
-            _ => unreachable!(),
+            0 => Ok(Qos::FireAndForget),
+            1 => Ok(Qos::AtLeastOnce),
+            2 => Ok(Qos::OnceAndOneOnly),
+            _ => Err(QosError::Invalid(i)),
         }
     }
 }
+impl TryFrom<i32> for Qos {
+    type Error = QosError;
+    fn try_from(i: i32) -> Result<Self, QosError> {
+        Qos::from_int(i)
+    }
+}
 
 impl ffiasync::MQTTAsync_connectOptions {
     pub fn new() -> Self {
@@ -73,12 +83,24 @@ impl ffiasync::MQTTAsync_connectOptions {
     }
 }
 
+// Message the broker publishes on our behalf when connection is lost without disconnect.
+// Payload is passed to paho as C string, so it cannot contain null characters.
+#[derive(Debug, Clone)]
+pub struct Will {
+    pub topic    : TopicName,
+    pub payload  : String,
+    pub qos      : Qos,
+    pub retained : bool,
+}
+
+#[derive(Debug, Clone)]
 pub struct AsyncConnectOptions {
     pub keep_alive_interval : i32,
     pub cleansession        : i32,
     pub max_in_flight       : i32,
     pub connect_timeout     : i32,
     pub retry_interval      : i32,
+    pub will                : Option<Will>,
 }
 impl AsyncConnectOptions {
     pub fn new() -> Self {
@@ -88,6 +110,7 @@ impl AsyncConnectOptions {
             max_in_flight       : 10,
             connect_timeout     : 30,
             retry_interval      : 0,
+            will                : None,
         }
     }
 }
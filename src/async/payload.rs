@@ -0,0 +1,96 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+
+// Reference counted message payload, clones share the same bytes.
+// Received payload is copied once out of paho buffer, after that fan-out to consumers only bumps the counter.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Payload {
+    data: Arc<Vec<u8>>,
+}
+
+impl Payload {
+    pub fn new() -> Self {
+        Payload::default()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Takes the bytes without copying if this is the only reference.
+    pub fn into_vec(self) -> Vec<u8> {
+        match Arc::try_unwrap(self.data) {
+            Ok(data)    => data,
+            Err(shared) => (*shared).clone(),
+        }
+    }
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl AsRef<[u8]> for Payload {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(data: Vec<u8>) -> Self {
+        Payload { data: Arc::new(data) }
+    }
+}
+
+impl<'a> From<&'a [u8]> for Payload {
+    fn from(data: &'a [u8]) -> Self {
+        Payload { data: Arc::new(data.to_vec()) }
+    }
+}
+
+impl From<String> for Payload {
+    fn from(data: String) -> Self {
+        Payload { data: Arc::new(data.into_bytes()) }
+    }
+}
+
+impl<'a> From<&'a str> for Payload {
+    fn from(data: &'a str) -> Self {
+        Payload { data: Arc::new(data.as_bytes().to_vec()) }
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Debug::fmt(&self.data[..], f)
+    }
+}
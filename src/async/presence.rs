@@ -0,0 +1,287 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::MqttError;
+use super::options::{Qos, Will, AsyncConnectOptions, AsyncDisconnectOptions};
+use super::subscription::Subscription;
+use super::topic::{TopicName, TopicFilter};
+
+
+pub const ONLINE: &'static str = "online";
+pub const OFFLINE: &'static str = "offline";
+
+// Announces our presence with retained messages on a status topic. The broker publishes
+// the offline status as will when connection is lost, a heartbeat republishes online status periodically.
+pub struct Presence {
+    topic      : TopicName,
+    online     : String,
+    offline    : String,
+    qos        : Qos,
+    heartbeat  : Option<Duration>,
+    last_beat  : Option<Instant>,
+}
+
+impl Presence {
+    pub fn new(topic: TopicName) -> Self {
+        Presence {
+            topic     : topic,
+            online    : ONLINE.to_string(),
+            offline   : OFFLINE.to_string(),
+            qos       : Qos::AtLeastOnce,
+            heartbeat : None,
+            last_beat : None,
+        }
+    }
+
+    pub fn payloads<S: Into<String>>(mut self, online: S, offline: S) -> Self {
+        self.online = online.into();
+        self.offline = offline.into();
+        self
+    }
+
+    pub fn qos(mut self, qos: Qos) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    pub fn heartbeat(mut self, interval: Option<Duration>) -> Self {
+        self.heartbeat = interval;
+        self
+    }
+
+    pub fn topic(&self) -> &TopicName {
+        &self.topic
+    }
+
+    pub fn will(&self) -> Will {
+        Will {
+            topic    : self.topic.clone(),
+            payload  : self.offline.clone(),
+            qos      : self.qos,
+            retained : true,
+        }
+    }
+
+    // Connects with offline status as will and publishes online status.
+    pub fn connect(&mut self, client: &mut AsyncClient, options: &AsyncConnectOptions) -> Result<(), MqttError> {
+        let mut options = options.clone();
+        options.will = Some(self.will());
+        try!(client.connect(&options));
+        self.publish_online(client)
+    }
+
+    // Republishes online status if heartbeat interval has passed, call it regularly. Returns true if published.
+    pub fn refresh(&mut self, client: &mut AsyncClient) -> Result<bool, MqttError> {
+        let due = match (self.heartbeat, self.last_beat) {
+            (Some(interval), Some(last)) => last.elapsed() >= interval,
+            (Some(_),        None      ) => true,
+            (None,           _         ) => false,
+        };
+        if due {
+            try!(self.publish_online(client));
+        }
+        Ok(due)
+    }
+
+    // Publishes offline status before disconnecting, the will is not sent on graceful disconnect.
+    pub fn disconnect(&mut self, client: &mut AsyncClient, options: &AsyncDisconnectOptions) -> Result<(), MqttError> {
+        try!(client.send(self.offline.as_bytes(), &self.topic, self.qos, true));
+        self.last_beat = None;
+        client.disconnect(options)
+    }
+
+    fn publish_online(&mut self, client: &mut AsyncClient) -> Result<(), MqttError> {
+        try!(client.send(self.online.as_bytes(), &self.topic, self.qos, true));
+        self.last_beat = Some(Instant::now());
+        Ok(())
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerStatus {
+    Online,
+    Offline,
+}
+
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub status    : PeerStatus,
+    pub since     : Instant,
+    pub last_seen : Instant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresenceChange {
+    pub topic    : String,
+    pub previous : Option<PeerStatus>,
+    pub current  : Option<PeerStatus>,  // None when status was cleared
+}
+
+// Presence of peers keyed by their status topics. With a timeout, online peers that have not
+// refreshed their status for that long are considered offline.
+pub struct PeerTracker {
+    pub timeout : Option<Duration>,
+    online      : String,
+    offline     : String,
+    peers       : BTreeMap<String, Peer>,
+}
+
+impl PeerTracker {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        PeerTracker {
+            timeout : timeout,
+            online  : ONLINE.to_string(),
+            offline : OFFLINE.to_string(),
+            peers   : BTreeMap::new(),
+        }
+    }
+
+    pub fn payloads<S: Into<String>>(mut self, online: S, offline: S) -> Self {
+        self.online = online.into();
+        self.offline = offline.into();
+        self
+    }
+
+    // Messages with unknown payload are ignored, empty payload removes the peer.
+    pub fn update(&mut self, topic: &str, payload: &[u8]) -> Option<PresenceChange> {
+        let now = Instant::now();
+        let status = if payload.is_empty() {
+            None
+        } else if payload == self.online.as_bytes() {
+            Some(PeerStatus::Online)
+        } else if payload == self.offline.as_bytes() {
+            Some(PeerStatus::Offline)
+        } else {
+            return None
+        };
+
+        let previous = match status {
+            None => self.peers.remove(topic).map(|peer| peer.status),
+            Some(status) => match self.peers.get_mut(topic) {
+                Some(peer) => {
+                    let previous = peer.status;
+                    if previous != status {
+                        peer.status = status;
+                        peer.since = now;
+                    }
+                    peer.last_seen = now;
+                    Some(previous)
+                }
+                None => {
+                    self.peers.insert(topic.to_string(), Peer {
+                        status    : status,
+                        since     : now,
+                        last_seen : now,
+                    });
+                    None
+                }
+            },
+        };
+        if previous == status {
+            None
+        } else {
+            Some(PresenceChange {
+                topic    : topic.to_string(),
+                previous : previous,
+                current  : status,
+            })
+        }
+    }
+
+    // Marks online peers silent for longer than timeout as offline.
+    pub fn expire(&mut self) -> Vec<PresenceChange> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None          => return Vec::new(),
+        };
+        let now = Instant::now();
+        let mut changes = Vec::new();
+        for (topic, peer) in self.peers.iter_mut() {
+            if peer.status == PeerStatus::Online && now.duration_since(peer.last_seen) > timeout {
+                peer.status = PeerStatus::Offline;
+                peer.since = now;
+                changes.push(PresenceChange {
+                    topic    : topic.clone(),
+                    previous : Some(PeerStatus::Online),
+                    current  : Some(PeerStatus::Offline),
+                });
+            }
+        }
+        changes
+    }
+
+    pub fn status(&self, topic: &str) -> Option<PeerStatus> {
+        self.peers.get(topic).map(|peer| peer.status)
+    }
+
+    pub fn peers(&self) -> &BTreeMap<String, Peer> {
+        &self.peers
+    }
+
+    pub fn online(&self) -> Vec<&str> {
+        self.peers.iter().filter(|&(_, peer)| peer.status == PeerStatus::Online).map(|(topic, _)| &topic[..]).collect()
+    }
+}
+
+// Tracks presence of peers whose status topics match a filter, e.g. "devices/+/status".
+pub struct PresenceWatcher {
+    subscription : Subscription,
+    tracker      : PeerTracker,
+}
+
+impl PresenceWatcher {
+    pub fn new(client: &mut AsyncClient, filter: &TopicFilter, tracker: PeerTracker) -> Result<Self, MqttError> {
+        let mut subscription = try!(client.subscribe(filter, Qos::AtLeastOnce));
+        subscription.set_unsubscribe_on_drop(true);
+        Ok(PresenceWatcher {
+            subscription : subscription,
+            tracker      : tracker,
+        })
+    }
+
+    pub fn apply(&mut self, msg: &Message) -> Option<PresenceChange> {
+        self.tracker.update(&msg.topic, &msg.payload)
+    }
+
+    pub fn poll(&mut self, timeout_ms: u32) -> Vec<PresenceChange> {
+        let mut changes = Vec::new();
+        for msg in self.subscription.messages(Some(timeout_ms)) {
+            if let Some(change) = self.apply(&msg) {
+                changes.push(change);
+            }
+        }
+        changes.extend(self.tracker.expire());
+        changes
+    }
+
+    pub fn tracker(&self) -> &PeerTracker {
+        &self.tracker
+    }
+}
@@ -0,0 +1,393 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::error::{MqttError, RateLimitError};
+use super::options::Qos;
+use super::payload::Payload;
+use super::topic::{TopicName, TopicFilter, TopicTree};
+
+
+// Sustained rate in messages per second and number of messages that may be sent at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    rate  : f64,
+    burst : u32,
+}
+
+impl Limit {
+    pub fn new(rate: f64, burst: u32) -> Result<Self, RateLimitError> {
+        if rate.is_nan() || rate <= 0.0 {
+            return Err(RateLimitError::InvalidLimit)
+        }
+        Ok(Limit {
+            rate  : rate,
+            burst : if burst == 0 { 1 } else { burst },
+        })
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+}
+
+// What send does when a limit is exceeded. Queue holds up to the given number of messages
+// which are sent in order as tokens become available. Nothing drains the queue on its own,
+// queued messages go out on the next send or flush_rate_limited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatePolicy {
+    Block,
+    FailFast,
+    Queue(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateStats {
+    pub filter  : Option<String>,  // None for the global limit
+    pub limit   : Limit,
+    pub rate    : f64,             // messages sent during the last second
+    pub tokens  : f64,
+    pub limited : u64,             // messages that had to wait, were queued or rejected
+}
+
+struct Bucket {
+    limit   : Limit,
+    tokens  : f64,
+    updated : Instant,
+    sent    : VecDeque<Instant>,
+    limited : u64,
+}
+
+impl Bucket {
+    fn new(limit: Limit) -> Self {
+        Bucket {
+            limit   : limit,
+            tokens  : limit.burst as f64,
+            updated : Instant::now(),
+            sent    : VecDeque::new(),
+            limited : 0,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        self.updated = now;
+        while let Some(&oldest) = self.sent.front() {
+            if now.duration_since(oldest) < Duration::from_secs(1) {
+                break
+            }
+            self.sent.pop_front();
+        }
+    }
+
+    fn wait(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::from_secs(0)
+        } else {
+            let secs = (1.0 - self.tokens) / self.limit.rate;
+            Duration::new(secs as u64, (secs.fract() * 1e9) as u32)
+        }
+    }
+
+    fn take(&mut self, now: Instant) {
+        self.tokens -= 1.0;
+        self.sent.push_back(now);
+    }
+
+    fn stats(&self, filter: Option<String>) -> RateStats {
+        RateStats {
+            filter  : filter,
+            limit   : self.limit,
+            rate    : self.sent.len() as f64,
+            tokens  : self.tokens,
+            limited : self.limited,
+        }
+    }
+}
+
+struct Queued {
+    data     : Payload,
+    topic    : TopicName,
+    qos      : Qos,
+    retained : bool,
+}
+
+struct State {
+    policy  : RatePolicy,
+    global  : Option<Bucket>,
+    buckets : HashMap<String, Bucket>,
+    filters : TopicTree<String>,
+    queue   : VecDeque<Queued>,
+}
+
+impl State {
+    // Takes a token from the global bucket and every bucket whose filter matches topic,
+    // or returns how long to wait until all of them have one.
+    fn acquire(&mut self, topic: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let filters: Vec<String> = self.filters.matches(topic).into_iter().cloned().collect();
+        let mut wait = Duration::from_secs(0);
+        {
+            let buckets = &mut self.buckets;
+            let mut matching: Vec<&mut Bucket> = buckets.iter_mut()
+                .filter(|&(filter, _)| filters.contains(filter))
+                .map(|(_, bucket)| bucket)
+                .collect();
+            if let Some(ref mut global) = self.global {
+                matching.push(global);
+            }
+            for bucket in matching.iter_mut() {
+                bucket.refill(now);
+                wait = wait.max(bucket.wait());
+            }
+            if wait == Duration::from_secs(0) {
+                for bucket in matching.iter_mut() {
+                    bucket.take(now);
+                }
+                return Ok(())
+            }
+        }
+        Err(wait)
+    }
+
+    // Counts a limited message against the buckets that are out of tokens.
+    fn mark_limited(&mut self, topic: &str) {
+        let filters: Vec<String> = self.filters.matches(topic).into_iter().cloned().collect();
+        for (filter, bucket) in self.buckets.iter_mut() {
+            if filters.contains(filter) && bucket.tokens < 1.0 {
+                bucket.limited += 1;
+            }
+        }
+        if let Some(ref mut global) = self.global {
+            if global.tokens < 1.0 {
+                global.limited += 1;
+            }
+        }
+    }
+}
+
+// Token bucket limiter for outbound messages, with an optional global limit and limits per topic filter.
+// A message is sent only when the global bucket and all buckets of matching filters have a token.
+// Cheap handle, clones share buckets and queue so limits can be changed while the client uses it.
+#[derive(Clone)]
+pub struct RateLimiter {
+    state: Arc<Mutex<State>>,
+}
+
+impl RateLimiter {
+    pub fn new(policy: RatePolicy) -> Self {
+        RateLimiter {
+            state: Arc::new(Mutex::new(State {
+                policy  : policy,
+                global  : None,
+                buckets : HashMap::new(),
+                filters : TopicTree::new(),
+                queue   : VecDeque::new(),
+            }))
+        }
+    }
+
+    pub fn set_global(&self, limit: Option<Limit>) {
+        self.state.lock().unwrap().global = limit.map(Bucket::new);
+    }
+
+    // Replaces limit of the filter if it already has one.
+    pub fn add_limit(&self, filter: &TopicFilter, limit: Limit) {
+        let mut state = self.state.lock().unwrap();
+        state.filters.remove(filter);
+        state.filters.insert(filter, filter.as_str().to_string());
+        state.buckets.insert(filter.as_str().to_string(), Bucket::new(limit));
+    }
+
+    pub fn remove_limit(&self, filter: &TopicFilter) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.filters.remove(filter);
+        state.buckets.remove(filter.as_str()).is_some()
+    }
+
+    pub fn policy(&self) -> RatePolicy {
+        self.state.lock().unwrap().policy
+    }
+
+    pub fn set_policy(&self, policy: RatePolicy) {
+        self.state.lock().unwrap().policy = policy;
+    }
+
+    // Global limit first, then per filter limits sorted by filter.
+    pub fn stats(&self) -> Vec<RateStats> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let mut stats = Vec::new();
+        if let Some(ref mut global) = state.global {
+            global.refill(now);
+            stats.push(global.stats(None));
+        }
+        let mut filters: Vec<RateStats> = state.buckets.iter_mut().map(|(filter, bucket)| {
+            bucket.refill(now);
+            bucket.stats(Some(filter.clone()))
+        }).collect();
+        filters.sort_by(|a, b| a.filter.cmp(&b.filter));
+        stats.extend(filters);
+        stats
+    }
+
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    // Sends through send according to policy. Queued messages go out first so order is kept,
+    // a queued message waits for its own topic's tokens and holds back the ones behind it.
+    pub fn send_with<F>(&self, data: &[u8], topic: &TopicName, qos: Qos, retained: bool, send: F) -> Result<(), MqttError>
+        where F: FnMut(&[u8], &TopicName, Qos, bool) -> Result<(), MqttError> {
+        let policy = self.policy();
+        self.send_policy_with(policy, data, topic, qos, retained, send)
+    }
+
+    // Like send_with but never takes ownership of the message. Under RatePolicy::Queue a message that
+    // can't go out right away fails with Exceeded, for callers that keep the message themselves.
+    pub fn send_unqueued_with<F>(&self, data: &[u8], topic: &TopicName, qos: Qos, retained: bool, mut send: F) -> Result<(), MqttError>
+        where F: FnMut(&[u8], &TopicName, Qos, bool) -> Result<(), MqttError> {
+        match self.policy() {
+            RatePolicy::Queue(_) => {
+                // messages queued earlier go first, failure belongs to them
+                if let Err(e) = self.flush_with(false, &mut send) {
+                    warn!("sending rate limited message failed: {}", e);
+                }
+                {
+                    let mut state = self.state.lock().unwrap();
+                    if !state.queue.is_empty() {
+                        state.mark_limited(topic);
+                        return Err(MqttError::RateLimit(RateLimitError::Exceeded(topic.as_str().to_string(), Duration::from_secs(0))))
+                    }
+                }
+                self.send_policy_with(RatePolicy::FailFast, data, topic, qos, retained, send)
+            }
+            policy => self.send_policy_with(policy, data, topic, qos, retained, send),
+        }
+    }
+
+    fn send_policy_with<F>(&self, policy: RatePolicy, data: &[u8], topic: &TopicName, qos: Qos, retained: bool, mut send: F) -> Result<(), MqttError>
+        where F: FnMut(&[u8], &TopicName, Qos, bool) -> Result<(), MqttError> {
+        match policy {
+            RatePolicy::Block => {
+                let mut first = true;
+                loop {
+                    let acquired = {
+                        let mut state = self.state.lock().unwrap();
+                        let acquired = state.acquire(topic);
+                        if acquired.is_err() && first {
+                            state.mark_limited(topic);
+                        }
+                        acquired
+                    };
+                    match acquired {
+                        Ok(())    => return send(data, topic, qos, retained),
+                        Err(wait) => thread::sleep(wait),
+                    }
+                    first = false;
+                }
+            }
+            RatePolicy::FailFast => {
+                let acquired = {
+                    let mut state = self.state.lock().unwrap();
+                    let acquired = state.acquire(topic);
+                    if acquired.is_err() {
+                        state.mark_limited(topic);
+                    }
+                    acquired
+                };
+                match acquired {
+                    Ok(())    => send(data, topic, qos, retained),
+                    Err(wait) => Err(MqttError::RateLimit(RateLimitError::Exceeded(topic.as_str().to_string(), wait))),
+                }
+            }
+            RatePolicy::Queue(capacity) => {
+                {
+                    let mut state = self.state.lock().unwrap();
+                    if state.queue.is_empty() && state.acquire(topic).is_ok() {
+                        drop(state);
+                        return send(data, topic, qos, retained)
+                    }
+                    state.mark_limited(topic);
+                    if state.queue.len() >= capacity {
+                        return Err(MqttError::RateLimit(RateLimitError::QueueFull(topic.as_str().to_string())))
+                    }
+                    debug!("rate limited, queueing message to {}", topic);
+                    state.queue.push_back(Queued {
+                        data     : Payload::from(data),
+                        topic    : topic.clone(),
+                        qos      : qos,
+                        retained : retained,
+                    });
+                }
+                // failure belongs to an earlier message which stays queued, flush_with reports it
+                if let Err(e) = self.flush_with(false, &mut send) {
+                    warn!("sending rate limited message failed: {}", e);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Sends queued messages that tokens allow, or all of them if wait is true. Returns number of messages sent.
+    // Stops at a message that fails to send, keeps it at the front of the queue and returns its error.
+    pub fn flush_with<F>(&self, wait: bool, mut send: F) -> Result<usize, MqttError>
+        where F: FnMut(&[u8], &TopicName, Qos, bool) -> Result<(), MqttError> {
+        let mut sent = 0;
+        loop {
+            let next = {
+                let mut state = self.state.lock().unwrap();
+                let acquired = match state.queue.front() {
+                    Some(queued) => {
+                        let topic = queued.topic.clone();
+                        state.acquire(&topic)
+                    }
+                    None => return Ok(sent),
+                };
+                match acquired {
+                    Ok(())               => state.queue.pop_front(),
+                    Err(delay) if wait   => { drop(state); thread::sleep(delay); continue },
+                    Err(_)               => return Ok(sent),
+                }
+            };
+            if let Some(queued) = next {
+                if let Err(e) = send(&queued.data, &queued.topic, queued.qos, queued.retained) {
+                    self.state.lock().unwrap().queue.push_front(queued);
+                    return Err(e)
+                }
+                sent += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+use super::Message;
+use super::topic::{TopicFilter, TopicTree};
+
+
+pub type RouteId = usize;
+
+struct Route {
+    id      : RouteId,
+    handler : Box<dyn Fn(&Message) + Send>,
+}
+
+struct Routes {
+    next_id : RouteId,
+    tree    : TopicTree<Route>,
+}
+
+// Calls every handler whose topic filter matches the message topic.
+// Router is a cheap handle, clones share the same routes. Handlers are called while routes are locked,
+// therefore handlers must not add or remove routes themselves. A panicking handler is logged and the others still run.
+#[derive(Clone)]
+pub struct Router {
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router {
+            routes: Arc::new(Mutex::new(Routes {
+                next_id : 0,
+                tree    : TopicTree::new(),
+            }))
+        }
+    }
+
+    pub fn add<F>(&self, filter: &TopicFilter, handler: F) -> RouteId where F: Fn(&Message) + Send + 'static {
+        let mut routes = self.routes.lock().unwrap();
+        let id = routes.next_id;
+        routes.next_id += 1;
+        routes.tree.insert(filter, Route {
+            id      : id,
+            handler : Box::new(handler),
+        });
+        id
+    }
+
+    pub fn remove(&self, id: RouteId) -> bool {
+        let mut routes = self.routes.lock().unwrap();
+        let before = routes.tree.len();
+        routes.tree.retain(|_, route| route.id != id);
+        routes.tree.len() != before
+    }
+
+    // Returns number of handlers that were called.
+    pub fn dispatch(&self, msg: &Message) -> usize {
+        let routes = self.routes.lock().unwrap();
+        let matched = routes.tree.matches(&msg.topic);
+        for route in matched.iter() {
+            // a panic here would poison routes for good
+            if panic::catch_unwind(AssertUnwindSafe(|| (route.handler)(msg))).is_err() {
+                error!("route {} panicked on {}", route.id, msg.topic);
+            }
+        }
+        matched.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.lock().unwrap().tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
+}
@@ -0,0 +1,203 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::time::{Duration, Instant};
+
+use super::Message;
+use super::builder::MessageBuilder;
+use super::wire::{new_id, put_u32, put_u64, get_u32, get_u64};
+use super::client::AsyncClient;
+use super::error::{MqttError, RpcError};
+use super::local::LocalBroker;
+use super::options::Qos;
+use super::payload::Payload;
+use super::subscription::Subscription;
+use super::topic::{TopicName, TopicFilter};
+
+
+// What RPC needs from a connection, implemented by AsyncClient and LocalBroker.
+pub trait Transport {
+    fn publish(&mut self, message: &Message) -> Result<(), MqttError>;
+    fn subscribe(&mut self, topic: &TopicFilter, qos: Qos) -> Result<Subscription, MqttError>;
+}
+
+impl Transport for AsyncClient {
+    fn publish(&mut self, message: &Message) -> Result<(), MqttError> {
+        AsyncClient::publish(self, message)
+    }
+    fn subscribe(&mut self, topic: &TopicFilter, qos: Qos) -> Result<Subscription, MqttError> {
+        AsyncClient::subscribe(self, topic, qos)
+    }
+}
+
+impl Transport for LocalBroker {
+    fn publish(&mut self, message: &Message) -> Result<(), MqttError> {
+        LocalBroker::publish(self, message)
+    }
+    fn subscribe(&mut self, topic: &TopicFilter, qos: Qos) -> Result<Subscription, MqttError> {
+        LocalBroker::subscribe(self, topic, qos)
+    }
+}
+
+
+// MQTT 3.1.1 has no message properties, so correlation data travels in front of the payload:
+//   "MQR" | version | correlation id (u64 BE) | reply topic length (u32 BE) | reply topic | body
+// Responses carry the request's correlation id and an empty reply topic.
+const MAGIC: &'static [u8] = b"MQR";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 3 + 1 + 8 + 4;
+
+fn encode(id: u64, reply_topic: &str, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + reply_topic.len() + body.len());
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    put_u64(&mut out, id);
+    put_u32(&mut out, reply_topic.len() as u32);
+    out.extend_from_slice(reply_topic.as_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+// Returns correlation id, reply topic (None in responses) and the message with envelope removed.
+fn decode(msg: &Message) -> Result<(u64, Option<TopicName>, Message), RpcError> {
+    let data: &[u8] = &msg.payload;
+    if data.len() < HEADER_LEN || !data.starts_with(MAGIC) || data[3] != VERSION {
+        return Err(RpcError::Malformed)
+    }
+    let id = get_u64(&data[4..12]);
+    let topic_len = get_u32(&data[12..16]) as usize;
+    if data.len() - HEADER_LEN < topic_len {
+        return Err(RpcError::Malformed)
+    }
+    let reply_topic = match topic_len {
+        0 => None,
+        _ => {
+            let topic = try!(String::from_utf8(data[HEADER_LEN..HEADER_LEN + topic_len].to_vec()).map_err(|_| RpcError::Malformed));
+            Some(try!(TopicName::new(topic).map_err(|_| RpcError::Malformed)))
+        }
+    };
+    let mut body = msg.clone();
+    body.payload = Payload::from(&data[HEADER_LEN + topic_len..]);
+    Ok((id, reply_topic, body))
+}
+
+
+// Sends requests and waits for their responses on its own reply topic.
+// Reply topic should be unique to the requester, e.g. contain the client id.
+pub struct Requester {
+    reply_topic  : TopicName,
+    qos          : Qos,
+    subscription : Subscription,
+}
+
+impl Requester {
+    pub fn new<T: Transport>(transport: &mut T, reply_topic: TopicName, qos: Qos) -> Result<Self, MqttError> {
+        let filter = TopicFilter::from(reply_topic.clone());
+        let mut subscription = try!(transport.subscribe(&filter, qos));
+        subscription.set_unsubscribe_on_drop(true);
+        Ok(Requester {
+            reply_topic  : reply_topic,
+            qos          : qos,
+            subscription : subscription,
+        })
+    }
+
+    pub fn reply_topic(&self) -> &TopicName {
+        &self.reply_topic
+    }
+
+    // Publishes data to topic and returns the response with envelope removed.
+    // Responses to earlier requests that timed out are discarded.
+    pub fn request<T: Transport, D: AsRef<[u8]>>(&mut self, transport: &mut T, topic: &TopicName, data: D, timeout_ms: u32) -> Result<Message, MqttError> {
+        let id = new_id();
+        let request = MessageBuilder::new(topic.clone())
+            .payload(encode(id, self.reply_topic.as_str(), data.as_ref()))
+            .qos(self.qos)
+            .build();
+        try!(transport.publish(&request));
+
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(MqttError::Rpc(RpcError::Timeout(id)))
+            }
+            let left = deadline - now;
+            let left_ms = left.as_secs() as u32 * 1000 + left.subsec_millis() + 1;
+            let msg = match self.subscription.messages(Some(left_ms)).next() {
+                Some(msg) => msg,
+                None      => return Err(MqttError::Rpc(RpcError::Timeout(id))),
+            };
+            match decode(&msg) {
+                Ok((response_id, None, response)) if response_id == id => return Ok(response),
+                Ok((response_id, _, _)) => debug!("discarding response {} on {}", response_id, msg.topic),
+                Err(e)                  => warn!("discarding response on {}: {}", msg.topic, e),
+            }
+        }
+    }
+}
+
+
+// Receives requests on a topic filter and publishes handler results to their reply topics.
+pub struct Responder {
+    qos          : Qos,
+    subscription : Subscription,
+}
+
+impl Responder {
+    pub fn new<T: Transport>(transport: &mut T, topic: &TopicFilter, qos: Qos) -> Result<Self, MqttError> {
+        let mut subscription = try!(transport.subscribe(topic, qos));
+        subscription.set_unsubscribe_on_drop(true);
+        Ok(Responder {
+            qos          : qos,
+            subscription : subscription,
+        })
+    }
+
+    pub fn topic(&self) -> &TopicFilter {
+        self.subscription.topic()
+    }
+
+    // Handles requests until none arrives for timeout_ms, returns number of requests answered.
+    // Handler gets the request with envelope removed. Messages that are not requests are skipped.
+    pub fn poll<T, F, P>(&mut self, transport: &mut T, timeout_ms: u32, mut handler: F) -> Result<usize, MqttError>
+        where T: Transport, F: FnMut(&Message) -> P, P: Into<Payload> {
+        let mut answered = 0;
+        for msg in self.subscription.messages(Some(timeout_ms)) {
+            let (id, reply_topic, request) = match decode(&msg) {
+                Ok((id, Some(reply_topic), request)) => (id, reply_topic, request),
+                Ok(_)  => { warn!("request on {} has no reply topic", msg.topic); continue },
+                Err(e) => { warn!("discarding request on {}: {}", msg.topic, e); continue },
+            };
+            let body = handler(&request).into();
+            let response = MessageBuilder::new(reply_topic)
+                .payload(encode(id, "", &body))
+                .qos(self.qos)
+                .build();
+            try!(transport.publish(&response));
+            answered += 1;
+        }
+        Ok(answered)
+    }
+}
@@ -0,0 +1,301 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload as AeadPayload};
+use ed25519_dalek::{Signer, Verifier};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::{MqttError, SecurityError};
+use super::options::Qos;
+use super::payload::Payload;
+use super::topic::{TopicName, TopicTree};
+use super::wire::{put_u64, get_u64};
+
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+// Envelope layout:
+//   "MQS" | version | flags | timestamp ms (u64 BE) | nonce (12) | body | signature
+// Signature covers topic, header and body, so a message can not be replayed on another topic.
+// When encrypted, body is ChaCha20-Poly1305 ciphertext with topic and header as associated data.
+const MAGIC: &'static [u8] = b"MQS";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 3 + 1 + 1 + 8 + 12;
+const FLAG_ENCRYPTED: u8 = 0x01;
+const FLAG_HMAC: u8 = 0x02;
+const FLAG_ED25519: u8 = 0x04;
+const HMAC_LEN: usize = 32;
+const ED25519_LEN: usize = 64;
+
+#[derive(Clone)]
+pub enum SignatureKey {
+    // Shared secret, both ends can sign and verify.
+    Hmac(Vec<u8>),
+    // Private key, can sign and verify.
+    Ed25519(SigningKey),
+    // Public key of a peer, can only verify.
+    Ed25519Verify(VerifyingKey),
+}
+
+// Keys used for topics matching one filter. Encryption key is optional, signing is not.
+#[derive(Clone)]
+pub struct TopicKeys {
+    pub signature  : SignatureKey,
+    pub encryption : Option<[u8; 32]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureKind {
+    Hmac,
+    Ed25519,
+}
+
+// Result of successful verification of a received message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    pub signature : SignatureKind,
+    pub encrypted : bool,
+    pub timestamp : u64,
+    pub nonce     : [u8; 12],
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifiedMessage {
+    pub message      : Message,
+    pub verification : Verification,
+}
+
+fn now_ms() -> u64 {
+    let t = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    t.as_secs() * 1000 + t.subsec_millis() as u64
+}
+
+fn hmac(key: &[u8], topic: &str, data: &[u8]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(topic.as_bytes());
+    mac.update(&[0]);
+    mac.update(data);
+    mac
+}
+
+fn signed_data(topic: &str, data: &[u8]) -> Vec<u8> {
+    let mut signed = Vec::with_capacity(topic.len() + 1 + data.len());
+    signed.extend_from_slice(topic.as_bytes());
+    signed.push(0);
+    signed.extend_from_slice(data);
+    signed
+}
+
+// Signs and optionally encrypts outgoing payloads, verifies and decrypts received ones.
+// Keys are looked up by topic, the longest matching filter wins. Received messages older or newer than
+// max_skew_ms are rejected and nonces seen within that window are remembered to reject replays.
+pub struct SecureLayer {
+    pub max_skew_ms : u64,
+    keys            : TopicTree<(String, TopicKeys)>,
+    seen            : HashMap<[u8; 12], u64>,
+    seen_order      : VecDeque<([u8; 12], u64)>,
+}
+
+impl SecureLayer {
+    pub fn new() -> Self {
+        SecureLayer {
+            max_skew_ms : 60_000,
+            keys        : TopicTree::new(),
+            seen        : HashMap::new(),
+            seen_order  : VecDeque::new(),
+        }
+    }
+
+    // Returns false if filter is not valid.
+    pub fn add_keys(&mut self, filter: &str, keys: TopicKeys) -> bool {
+        self.keys.insert(filter, (filter.to_string(), keys))
+    }
+
+    fn keys_for(&self, topic: &str) -> Result<&TopicKeys, SecurityError> {
+        self.keys.matches(topic).into_iter()
+            .max_by_key(|&&(ref filter, _)| filter.len())
+            .map(|&(_, ref keys)| keys)
+            .ok_or_else(|| SecurityError::NoKey(topic.to_string()))
+    }
+
+    pub fn seal(&self, topic: &str, data: &[u8]) -> Result<Vec<u8>, SecurityError> {
+        let keys = try!(self.keys_for(topic));
+
+        let mut flags = match keys.signature {
+            SignatureKey::Hmac(_)          => FLAG_HMAC,
+            SignatureKey::Ed25519(_)       => FLAG_ED25519,
+            SignatureKey::Ed25519Verify(_) => return Err(SecurityError::CanNotSign(topic.to_string())),
+        };
+        if keys.encryption.is_some() {
+            flags |= FLAG_ENCRYPTED;
+        }
+
+        let mut nonce = [0u8; 12];
+        try!(::getrandom::fill(&mut nonce).map_err(|e| SecurityError::Random(e.to_string())));
+
+        let mut out = Vec::with_capacity(HEADER_LEN + data.len() + 16 + ED25519_LEN);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(flags);
+        put_u64(&mut out, now_ms());
+        out.extend_from_slice(&nonce);
+
+        match keys.encryption {
+            Some(ref key) => {
+                let cipher = ChaCha20Poly1305::new(key.into());
+                let aad = signed_data(topic, &out);
+                let body = try!(cipher.encrypt((&nonce).into(), AeadPayload { msg: data, aad: &aad }).map_err(|_| SecurityError::Encrypt));
+                out.extend_from_slice(&body);
+            }
+            None => out.extend_from_slice(data),
+        }
+
+        match keys.signature {
+            SignatureKey::Hmac(ref key) => {
+                let signature = hmac(key, topic, &out).finalize().into_bytes();
+                out.extend_from_slice(&signature);
+            }
+            SignatureKey::Ed25519(ref key) => {
+                let signature = key.sign(&signed_data(topic, &out));
+                out.extend_from_slice(&signature.to_bytes());
+            }
+            SignatureKey::Ed25519Verify(_) => unreachable!(),
+        }
+        Ok(out)
+    }
+
+    // Verifies signature, timestamp and nonce of a received message and decrypts its payload.
+    pub fn open(&mut self, msg: &Message) -> Result<VerifiedMessage, SecurityError> {
+        let data: &[u8] = &msg.payload;
+        if data.len() < HEADER_LEN || !data.starts_with(MAGIC) || data[3] != VERSION {
+            return Err(SecurityError::Malformed)
+        }
+        let flags = data[4];
+        let timestamp = get_u64(&data[5..13]);
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&data[13..HEADER_LEN]);
+
+        let (kind, signature_len) = match (flags & FLAG_HMAC != 0, flags & FLAG_ED25519 != 0) {
+            (true,  false) => (SignatureKind::Hmac, HMAC_LEN),
+            (false, true ) => (SignatureKind::Ed25519, ED25519_LEN),
+            _              => return Err(SecurityError::Malformed),
+        };
+        if data.len() < HEADER_LEN + signature_len {
+            return Err(SecurityError::Malformed)
+        }
+        let (signed, signature) = data.split_at(data.len() - signature_len);
+
+        let keys = try!(self.keys_for(&msg.topic)).clone();
+        let valid = match (kind, &keys.signature) {
+            (SignatureKind::Hmac, &SignatureKey::Hmac(ref key)) => {
+                hmac(key, &msg.topic, signed).verify_slice(signature).is_ok()
+            }
+            (SignatureKind::Ed25519, &SignatureKey::Ed25519(ref key)) => {
+                verify_ed25519(&key.verifying_key(), &msg.topic, signed, signature)
+            }
+            (SignatureKind::Ed25519, &SignatureKey::Ed25519Verify(ref key)) => {
+                verify_ed25519(key, &msg.topic, signed, signature)
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(SecurityError::BadSignature)
+        }
+
+        let now = now_ms();
+        let skew = if now > timestamp { now - timestamp } else { timestamp - now };
+        if skew > self.max_skew_ms {
+            return Err(SecurityError::Stale(timestamp))
+        }
+        try!(self.remember_nonce(nonce, now));
+
+        let encrypted = flags & FLAG_ENCRYPTED != 0;
+        let body = &signed[HEADER_LEN..];
+        let payload = if encrypted {
+            let key = match keys.encryption {
+                Some(ref key) => key,
+                None          => return Err(SecurityError::Decrypt),
+            };
+            let cipher = ChaCha20Poly1305::new(key.into());
+            let aad = signed_data(&msg.topic, &signed[..HEADER_LEN]);
+            Payload::from(try!(cipher.decrypt((&nonce).into(), AeadPayload { msg: body, aad: &aad }).map_err(|_| SecurityError::Decrypt)))
+        } else {
+            Payload::from(body)
+        };
+
+        let mut message = msg.clone();
+        message.payload = payload;
+        Ok(VerifiedMessage {
+            message      : message,
+            verification : Verification {
+                signature : kind,
+                encrypted : encrypted,
+                timestamp : timestamp,
+                nonce     : nonce,
+            }
+        })
+    }
+
+    fn remember_nonce(&mut self, nonce: [u8; 12], now: u64) -> Result<(), SecurityError> {
+        // forget nonces that are older than any message that could still pass the timestamp check
+        while let Some(&(old, seen_at)) = self.seen_order.front() {
+            if now.saturating_sub(seen_at) <= 2 * self.max_skew_ms {
+                break
+            }
+            self.seen_order.pop_front();
+            self.seen.remove(&old);
+        }
+        if self.seen.contains_key(&nonce) {
+            return Err(SecurityError::Replayed)
+        }
+        self.seen.insert(nonce, now);
+        self.seen_order.push_back((nonce, now));
+        Ok(())
+    }
+
+    pub fn send<D: AsRef<[u8]>>(&self, client: &mut AsyncClient, data: D, topic: &TopicName, qos: Qos, retained: bool) -> Result<(), MqttError> {
+        let sealed = try!(self.seal(topic, data.as_ref()));
+        client.send(sealed, topic, qos, retained)
+    }
+}
+
+impl Default for SecureLayer {
+    fn default() -> Self {
+        SecureLayer::new()
+    }
+}
+
+fn verify_ed25519(key: &VerifyingKey, topic: &str, signed: &[u8], signature: &[u8]) -> bool {
+    let mut bytes = [0u8; ED25519_LEN];
+    bytes.copy_from_slice(signature);
+    let signature = ::ed25519_dalek::Signature::from_bytes(&bytes);
+    key.verify(&signed_data(topic, signed), &signature).is_ok()
+}
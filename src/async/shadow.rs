@@ -0,0 +1,310 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use serde_json::{self, Value, Map};
+
+use super::Message;
+use super::client::AsyncClient;
+use super::error::{MqttError, PayloadError};
+use super::options::Qos;
+use super::subscription::Subscription;
+use super::topic::{TopicName, TopicFilter};
+
+
+// Shadow messages, <base> is the device's shadow topic:
+//   <base>/desired        {"version": 7, "state": {...}}   merge patch of desired state, version grows by one
+//   <base>/desired/full   {"version": 7, "state": {...}}   whole desired state, retained, for resync
+//   <base>/reported       {"version": 7, "state": {...}}   whole reported state, retained, version is the
+//                                                          desired version it was reported against
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShadowEvent {
+    // desired state differs from reported, these values must be applied
+    Delta(Value),
+    // desired update is not newer than the current desired state and was ignored
+    Conflict { current: u64, received: u64 },
+    // desired updates between current and received were missed, the update was ignored
+    // and the document must be resynced from the full desired state
+    Gap { current: u64, received: u64 },
+    Invalid(String),
+}
+
+// How long Shadow::new waits for the retained reported state.
+const REPORTED_QUIET_MS: u32 = 500;
+
+// Applies RFC 7386 JSON merge patch, null removes a member.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    match *patch {
+        Value::Object(ref members) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            let object = target.as_object_mut().unwrap();
+            for (key, value) in members.iter() {
+                if value.is_null() {
+                    object.remove(key);
+                } else {
+                    merge_patch(object.entry(key.clone()).or_insert(Value::Null), value);
+                }
+            }
+        }
+        _ => *target = patch.clone(),
+    }
+}
+
+// Members of desired that reported does not have or has with other value, nested objects are compared member by member.
+pub fn delta(desired: &Value, reported: &Value) -> Value {
+    let mut out = Map::new();
+    if let Some(desired) = desired.as_object() {
+        for (key, value) in desired.iter() {
+            match reported.get(key) {
+                Some(current) if current == value => {}
+                Some(current) if value.is_object() && current.is_object() => {
+                    let nested = delta(value, current);
+                    if nested.as_object().map_or(false, |members| !members.is_empty()) {
+                        out.insert(key.clone(), nested);
+                    }
+                }
+                _ => { out.insert(key.clone(), value.clone()); }
+            }
+        }
+    }
+    Value::Object(out)
+}
+
+fn is_empty(value: &Value) -> bool {
+    value.as_object().map_or(false, |members| members.is_empty())
+}
+
+// Version and state of a desired update or full desired state message.
+fn parse_desired(payload: &[u8]) -> Result<(u64, Value), ShadowEvent> {
+    let message: Value = match serde_json::from_slice(payload) {
+        Ok(message) => message,
+        Err(e)      => return Err(ShadowEvent::Invalid(e.to_string())),
+    };
+    let version = match message.get("version").and_then(Value::as_u64) {
+        Some(version) => version,
+        None          => return Err(ShadowEvent::Invalid("version is missing".to_string())),
+    };
+    match message.get("state") {
+        Some(state) if state.is_object() => Ok((version, state.clone())),
+        _ => Err(ShadowEvent::Invalid("state is missing".to_string())),
+    }
+}
+
+fn versioned(version: u64, state: &Value) -> Value {
+    let mut message = Map::new();
+    message.insert("version".to_string(), Value::from(version));
+    message.insert("state".to_string(), state.clone());
+    Value::Object(message)
+}
+
+// Local copy of desired and reported state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowDocument {
+    pub desired  : Value,
+    pub reported : Value,
+    pub version  : u64,
+}
+
+impl ShadowDocument {
+    pub fn new() -> Self {
+        ShadowDocument {
+            desired  : Value::Object(Map::new()),
+            reported : Value::Object(Map::new()),
+            version  : 0,
+        }
+    }
+
+    pub fn delta(&self) -> Value {
+        delta(&self.desired, &self.reported)
+    }
+
+    // Merges a desired update message, returns delta if something remains to be applied.
+    // Only the update following the current version is merged, it is a patch against that version.
+    pub fn apply_desired(&mut self, payload: &[u8]) -> Option<ShadowEvent> {
+        let (version, state) = match parse_desired(payload) {
+            Ok(update) => update,
+            Err(event) => return Some(event),
+        };
+        if version <= self.version {
+            return Some(ShadowEvent::Conflict { current: self.version, received: version })
+        }
+        if version != self.version + 1 {
+            return Some(ShadowEvent::Gap { current: self.version, received: version })
+        }
+
+        merge_patch(&mut self.desired, &state);
+        self.version = version;
+        self.changed()
+    }
+
+    // Replaces desired state with a full desired state message, returns delta if something remains to be applied.
+    pub fn resync(&mut self, payload: &[u8]) -> Option<ShadowEvent> {
+        let (version, state) = match parse_desired(payload) {
+            Ok(full)   => full,
+            Err(event) => return Some(event),
+        };
+        if version < self.version {
+            return Some(ShadowEvent::Conflict { current: self.version, received: version })
+        }
+
+        self.desired = state;
+        self.version = version;
+        self.changed()
+    }
+
+    fn changed(&self) -> Option<ShadowEvent> {
+        let delta = self.delta();
+        if is_empty(&delta) { None } else { Some(ShadowEvent::Delta(delta)) }
+    }
+
+    // Replaces reported state with a reported message published earlier, so reports made after
+    // a restart keep the members that are not patched. Desired version is not changed.
+    pub fn restore_reported(&mut self, payload: &[u8]) -> Option<ShadowEvent> {
+        match parse_desired(payload) {
+            Ok((_, state)) => {
+                self.reported = state;
+                None
+            }
+            Err(event) => Some(event),
+        }
+    }
+
+    // Merges patch into reported state and returns the message to publish.
+    pub fn report(&mut self, patch: &Value) -> Value {
+        merge_patch(&mut self.reported, patch);
+        versioned(self.version, &self.reported)
+    }
+
+    // Builds a desired update message for the next version, for the side that controls the device.
+    pub fn desire(&mut self, patch: &Value) -> Value {
+        merge_patch(&mut self.desired, patch);
+        self.version += 1;
+        versioned(self.version, patch)
+    }
+
+    // Full desired state message, the controlling side publishes it retained to <base>/desired/full
+    // along with every update.
+    pub fn full_desired(&self) -> Value {
+        versioned(self.version, &self.desired)
+    }
+}
+
+impl Default for ShadowDocument {
+    fn default() -> Self {
+        ShadowDocument::new()
+    }
+}
+
+// Keeps a shadow document in sync with the desired and reported topics of a device.
+// The retained full desired state is received on creation and on resync, the retained
+// reported state is read once on creation.
+pub struct Shadow {
+    desired_topic  : TopicName,
+    full_topic     : TopicName,
+    reported_topic : TopicName,
+    qos            : Qos,
+    subscription   : Subscription,
+    full           : Subscription,
+    document       : ShadowDocument,
+}
+
+impl Shadow {
+    // Subscribes to desired updates and full desired state of the shadow under base topic.
+    // Blocks until the retained reported state arrives, or for REPORTED_QUIET_MS if there is none.
+    pub fn new(client: &mut AsyncClient, base: &TopicName, qos: Qos) -> Result<Self, MqttError> {
+        let desired_topic = try!(TopicName::new(format!("{}/desired", base)));
+        let full_topic = try!(TopicName::new(format!("{}/desired/full", base)));
+        let reported_topic = try!(TopicName::new(format!("{}/reported", base)));
+        let full = try!(Shadow::subscribe(client, &full_topic, qos));
+        let subscription = try!(Shadow::subscribe(client, &desired_topic, qos));
+        let mut document = ShadowDocument::new();
+        let reported = try!(client.retained_snapshot(&TopicFilter::from(reported_topic.clone()), qos, REPORTED_QUIET_MS));
+        if let Some(msg) = reported.get(reported_topic.as_str()) {
+            if let Some(event) = document.restore_reported(&msg.payload) {
+                warn!("ignoring retained reported state on {}: {:?}", reported_topic, event);
+            }
+        }
+        Ok(Shadow {
+            desired_topic  : desired_topic,
+            full_topic     : full_topic,
+            reported_topic : reported_topic,
+            qos            : qos,
+            subscription   : subscription,
+            full           : full,
+            document       : document,
+        })
+    }
+
+    fn subscribe(client: &mut AsyncClient, topic: &TopicName, qos: Qos) -> Result<Subscription, MqttError> {
+        let mut subscription = try!(client.subscribe(&TopicFilter::from(topic.clone()), qos));
+        subscription.set_unsubscribe_on_drop(true);
+        Ok(subscription)
+    }
+
+    pub fn apply(&mut self, msg: &Message) -> Option<ShadowEvent> {
+        if msg.topic == self.desired_topic.as_str() {
+            self.document.apply_desired(&msg.payload)
+        } else if msg.topic == self.full_topic.as_str() {
+            self.document.resync(&msg.payload)
+        } else {
+            None
+        }
+    }
+
+    pub fn poll(&mut self, timeout_ms: u32) -> Vec<ShadowEvent> {
+        let mut events = Vec::new();
+        let full: Vec<Message> = self.full.messages(Some(0)).collect();
+        let updates: Vec<Message> = self.subscription.messages(Some(timeout_ms)).collect();
+        for msg in full.iter().chain(updates.iter()) {
+            if let Some(event) = self.apply(msg) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    // Subscribes to the full desired state again so the broker resends the retained message,
+    // call after ShadowEvent::Gap. The state arrives through poll.
+    pub fn resync(&mut self, client: &mut AsyncClient) -> Result<(), MqttError> {
+        // new handle first, so the filter stays subscribed when the old one drops
+        self.full = try!(Shadow::subscribe(client, &self.full_topic, self.qos));
+        Ok(())
+    }
+
+    // Merges patch into reported state and publishes the whole reported state retained.
+    pub fn report(&mut self, client: &mut AsyncClient, patch: &Value) -> Result<(), MqttError> {
+        let message = self.document.report(patch);
+        let data = try!(serde_json::to_vec(&message).map_err(|e| PayloadError::Encode(e.to_string())));
+        client.send(data, &self.reported_topic, self.qos, true)
+    }
+
+    pub fn document(&self) -> &ShadowDocument {
+        &self.document
+    }
+
+    pub fn delta(&self) -> Value {
+        self.document.delta()
+    }
+}
@@ -0,0 +1,118 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use ffiasync;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use super::Message;
+use super::dispatch::{Dispatcher, MessageQueue, SubscriberId};
+use super::iterator::AsyncClientIntoIterator;
+use super::topic::TopicFilter;
+
+
+// Raw paho handle that can be shared with subscriptions.
+// It is cleared by the client before the handle is destroyed.
+pub struct RawHandle(pub ffiasync::MQTTAsync);
+unsafe impl Send for RawHandle {}
+
+pub type SharedHandle = Arc<Mutex<Option<RawHandle>>>;
+
+
+// Receives only messages that match the topic filter it was created with, those messages
+// are not queued for AsyncClient::messages. Optionally unsubscribes the filter from the broker
+// when the last handle on the same filter is dropped.
+pub struct Subscription {
+    topic               : TopicFilter,
+    queue               : MessageQueue,
+    id                  : SubscriberId,
+    dispatcher          : Arc<Dispatcher>,
+    handle              : SharedHandle,
+    unsubscribe_on_drop : bool,
+}
+
+impl Subscription {
+    pub fn new(topic: TopicFilter, queue: MessageQueue, id: SubscriberId, dispatcher: Arc<Dispatcher>, handle: SharedHandle) -> Self {
+        dispatcher.retain_filter(&topic);
+        Subscription {
+            topic               : topic,
+            queue               : queue,
+            id                  : id,
+            dispatcher          : dispatcher,
+            handle              : handle,
+            unsubscribe_on_drop : false,
+        }
+    }
+
+    pub fn topic(&self) -> &TopicFilter {
+        &self.topic
+    }
+
+    pub fn messages(&self, timeout_ms: Option<u32>) -> AsyncClientIntoIterator {
+        AsyncClientIntoIterator::new(self.queue.clone(), timeout_ms)
+    }
+
+    // Collects retained messages by topic until the first non-retained message arrives
+    // or nothing arrives for quiet_ms.
+    pub fn retained(&self, quiet_ms: u32) -> BTreeMap<String, Message> {
+        let mut retained = BTreeMap::new();
+        for msg in self.messages(Some(quiet_ms)) {
+            if !msg.retained {
+                break
+            }
+            retained.insert(msg.topic.clone(), msg);
+        }
+        retained
+    }
+
+    pub fn set_unsubscribe_on_drop(&mut self, unsubscribe: bool) {
+        self.unsubscribe_on_drop = unsubscribe;
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.dispatcher.remove(self.id);
+
+        let last = self.dispatcher.release_filter(&self.topic);
+        if self.unsubscribe_on_drop && last {
+            // fire and forget, nobody is left to wait for the result
+            let handle = self.handle.lock().unwrap();
+            if let Some(RawHandle(h)) = *handle {
+                debug!("unsubscribe on drop..");
+                let c_topic = CString::new(self.topic.as_str()).unwrap();
+                let error = unsafe {
+                    ffiasync::MQTTAsync_unsubscribe(h, c_topic.as_ptr(), ptr::null_mut())
+                };
+                if error != 0 {
+                    warn!("unsubscribe on drop failed: {}", error);
+                } else {
+                    self.dispatcher.unsubscribed(&self.topic);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::error::TemplateError;
+use super::topic::{self, TopicName, TopicFilter};
+
+
+enum Level {
+    Literal(String),
+    Param(String),
+}
+
+// Structured topic such as "site/{site}/device/{id}/telemetry" where every `{name}` is one whole level.
+// Renders concrete topic names, converts to a subscription filter and extracts parameters from received topics.
+pub struct TopicTemplate {
+    template : String,
+    levels   : Vec<Level>,
+}
+
+// Implemented by user types that can be built from extracted topic parameters.
+pub trait FromTopicParams: Sized {
+    fn from_topic_params(params: &HashMap<String, String>) -> Result<Self, TemplateError>;
+}
+
+impl TopicTemplate {
+    pub fn new(template: &str) -> Result<Self, TemplateError> {
+        try!(topic::validate_topic(&template.replace('{', "").replace('}', "")));
+
+        let mut levels: Vec<Level> = Vec::new();
+        for level in template.split('/') {
+            if level.starts_with('{') && level.ends_with('}') && level.len() > 2 {
+                let name = &level[1..level.len()-1];
+                if name.contains(&['{', '}'][..]) {
+                    return Err(TemplateError::InvalidTemplate(template.to_string()))
+                }
+                if levels.iter().any(|l| match *l { Level::Param(ref n) => n == name, _ => false }) {
+                    return Err(TemplateError::DuplicateParam(name.to_string()))
+                }
+                levels.push(Level::Param(name.to_string()));
+            } else if level.contains(&['{', '}'][..]) {
+                return Err(TemplateError::InvalidTemplate(template.to_string()))
+            } else {
+                levels.push(Level::Literal(level.to_string()));
+            }
+        }
+
+        Ok(TopicTemplate {
+            template : template.to_string(),
+            levels   : levels,
+        })
+    }
+
+    pub fn params(&self) -> Vec<&str> {
+        self.levels.iter().filter_map(|l| match *l {
+            Level::Param(ref name) => Some(&name[..]),
+            Level::Literal(_)      => None,
+        }).collect()
+    }
+
+    // Parameter values must not contain level separator or wildcards.
+    pub fn render(&self, params: &[(&str, &str)]) -> Result<TopicName, TemplateError> {
+        let mut levels = Vec::with_capacity(self.levels.len());
+        for level in self.levels.iter() {
+            match *level {
+                Level::Literal(ref literal) => levels.push(&literal[..]),
+                Level::Param(ref name) => {
+                    let value = match params.iter().find(|&&(n, _)| n == name) {
+                        Some(&(_, value)) => value,
+                        None => return Err(TemplateError::MissingParam(name.clone())),
+                    };
+                    if value.contains(&['/', '+', '#', '\0'][..]) {
+                        return Err(TemplateError::InvalidValue(name.clone(), value.to_string()))
+                    }
+                    levels.push(value);
+                }
+            }
+        }
+        Ok(try!(TopicName::new(levels.join("/"))))
+    }
+
+    pub fn render_map(&self, params: &HashMap<String, String>) -> Result<TopicName, TemplateError> {
+        let params: Vec<(&str, &str)> = params.iter().map(|(k, v)| (&k[..], &v[..])).collect();
+        self.render(&params)
+    }
+
+    // Every parameter becomes '+' wildcard.
+    pub fn filter(&self) -> TopicFilter {
+        let levels: Vec<&str> = self.levels.iter().map(|l| match *l {
+            Level::Literal(ref literal) => &literal[..],
+            Level::Param(_)             => "+",
+        }).collect();
+        TopicFilter::new(levels.join("/")).unwrap()  // literals are validated in new()
+    }
+
+    // Returns None if topic does not match the template.
+    pub fn extract(&self, topic: &str) -> Option<HashMap<String, String>> {
+        let topic_levels: Vec<&str> = topic.split('/').collect();
+        if topic_levels.len() != self.levels.len() {
+            return None
+        }
+
+        let mut params = HashMap::new();
+        for (level, value) in self.levels.iter().zip(topic_levels.into_iter()) {
+            match *level {
+                Level::Literal(ref literal) => if literal != value { return None },
+                Level::Param(ref name)      => { params.insert(name.clone(), value.to_string()); },
+            }
+        }
+        Some(params)
+    }
+
+    pub fn extract_into<T: FromTopicParams>(&self, topic: &str) -> Result<T, TemplateError> {
+        match self.extract(topic) {
+            Some(params) => T::from_topic_params(&params),
+            None         => Err(TemplateError::NoMatch(topic.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+}
+
+impl fmt::Display for TopicTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(&self.template, f)
+    }
+}
+
+impl FromTopicParams for HashMap<String, String> {
+    fn from_topic_params(params: &HashMap<String, String>) -> Result<Self, TemplateError> {
+        Ok(params.clone())
+    }
+}
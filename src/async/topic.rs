@@ -0,0 +1,339 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use super::error::TopicError;
+
+
+// MQTT strings are prefixed with 16 bit length.
+pub const MAX_TOPIC_LEN: usize = 65535;
+
+fn validate_common(s: &str) -> Result<(), TopicError> {
+    if s.is_empty() {
+        Err(TopicError::Empty)
+    } else if s.len() > MAX_TOPIC_LEN {
+        Err(TopicError::TooLong(s.len()))
+    } else if s.contains('\0') {
+        Err(TopicError::NullCharacter)
+    } else {
+        Ok(())
+    }
+}
+
+// Topic name must be non-empty and must not contain wildcard characters.
+pub fn validate_topic(topic: &str) -> Result<(), TopicError> {
+    try!(validate_common(topic));
+    if topic.contains(&['+', '#'][..]) {
+        return Err(TopicError::WildcardInName)
+    }
+    Ok(())
+}
+
+// Topic filter must be non-empty, '+' and '#' must occupy a whole level and '#' must be the last level.
+pub fn validate_filter(filter: &str) -> Result<(), TopicError> {
+    try!(validate_common(filter));
+    let mut levels = filter.split('/').peekable();
+    while let Some(level) = levels.next() {
+        match level {
+            "#" => if levels.peek().is_some() { return Err(TopicError::InvalidWildcard) },
+            "+" => {},
+            _   => if level.contains(&['+', '#'][..]) { return Err(TopicError::InvalidWildcard) },
+        }
+    }
+    Ok(())
+}
+
+pub fn is_valid_topic(topic: &str) -> bool {
+    validate_topic(topic).is_ok()
+}
+
+pub fn is_valid_filter(filter: &str) -> bool {
+    validate_filter(filter).is_ok()
+}
+
+// Checks whether topic name matches subscription filter according to MQTT 3.1.1 section 4.7.
+// Level separator is '/', '+' matches exactly one level and '#' matches the parent level and
+// any number of levels below it. Topics starting with '$' are not matched by filters starting with a wildcard.
+// Invalid topics and filters never match.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    if !is_valid_filter(filter) || !is_valid_topic(topic) {
+        return false
+    }
+    if topic.starts_with('$') && (filter.starts_with('+') || filter.starts_with('#')) {
+        return false
+    }
+
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels  = topic.split('/');
+
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _          ) => return true,
+            (Some("+"), Some(_)    ) => continue,
+            (Some(f),   Some(t)    ) => if f != t { return false },
+            (None,      None       ) => return true,
+            _                        => return false,
+        }
+    }
+}
+
+
+struct Node<T> {
+    values   : Vec<T>,
+    children : HashMap<String, Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new() -> Self {
+        Node {
+            values   : Vec::new(),
+            children : HashMap::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.values.is_empty() && self.children.is_empty()
+    }
+
+    fn collect<'a>(&'a self, levels: &[&str], first: bool, found: &mut Vec<&'a T>) {
+        // '$' topics are hidden from wildcards on the first level
+        let wildcards = !(first && levels.first().map_or(false, |l| l.starts_with('$')));
+
+        if wildcards {
+            if let Some(node) = self.children.get("#") {
+                found.extend(node.values.iter());
+            }
+        }
+
+        match levels.split_first() {
+            None => found.extend(self.values.iter()),
+            Some((level, rest)) => {
+                if let Some(node) = self.children.get(*level) {
+                    node.collect(rest, false, found);
+                }
+                if wildcards {
+                    if let Some(node) = self.children.get("+") {
+                        node.collect(rest, false, found);
+                    }
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, levels: &[&str]) -> Vec<T> {
+        match levels.split_first() {
+            None => self.values.drain(..).collect(),
+            Some((level, rest)) => {
+                let (removed, prune) = match self.children.get_mut(*level) {
+                    Some(node) => {
+                        let removed = node.remove(rest);
+                        (removed, node.is_empty())
+                    }
+                    None => (Vec::new(), false),
+                };
+                if prune {
+                    self.children.remove(*level);
+                }
+                removed
+            }
+        }
+    }
+
+    fn retain<F>(&mut self, filter: &mut Vec<String>, f: &mut F) -> usize where F: FnMut(&str, &T) -> bool {
+        let joined = filter.join("/");
+        let before = self.values.len();
+        self.values.retain(|value| f(&joined, value));
+        let mut removed = before - self.values.len();
+
+        for (level, node) in self.children.iter_mut() {
+            filter.push(level.clone());
+            removed += node.retain(filter, f);
+            filter.pop();
+        }
+        self.children.retain(|_, node| !node.is_empty());
+        removed
+    }
+}
+
+// Stores values under topic filters and finds all values whose filter matches a topic name.
+// Lookup walks only the levels of the topic, which keeps matching cheap with thousands of filters.
+pub struct TopicTree<T> {
+    root : Node<T>,
+    len  : usize,
+}
+
+impl<T> TopicTree<T> {
+    pub fn new() -> Self {
+        TopicTree {
+            root : Node::new(),
+            len  : 0,
+        }
+    }
+
+    // Returns false and stores nothing if filter is not valid.
+    pub fn insert(&mut self, filter: &str, value: T) -> bool {
+        if !is_valid_filter(filter) {
+            return false
+        }
+        let mut node = &mut self.root;
+        for level in filter.split('/') {
+            node = node.children.entry(level.to_string()).or_insert_with(Node::new);
+        }
+        node.values.push(value);
+        self.len += 1;
+        true
+    }
+
+    // Removes and returns all values stored under exactly this filter.
+    pub fn remove(&mut self, filter: &str) -> Vec<T> {
+        let levels: Vec<&str> = filter.split('/').collect();
+        let removed = self.root.remove(&levels);
+        self.len -= removed.len();
+        removed
+    }
+
+    // Whether values are stored under exactly this filter.
+    pub fn contains(&self, filter: &str) -> bool {
+        let mut node = &self.root;
+        for level in filter.split('/') {
+            node = match node.children.get(level) {
+                Some(child) => child,
+                None        => return false,
+            };
+        }
+        !node.values.is_empty()
+    }
+
+    // Keeps only values for which f(filter, value) returns true.
+    pub fn retain<F>(&mut self, mut f: F) where F: FnMut(&str, &T) -> bool {
+        let mut filter = Vec::new();
+        let removed = self.root.retain(&mut filter, &mut f);
+        self.len -= removed;
+    }
+
+    // Returns values of all filters matching the topic name.
+    pub fn matches(&self, topic: &str) -> Vec<&T> {
+        let mut found = Vec::new();
+        if is_valid_topic(topic) {
+            let levels: Vec<&str> = topic.split('/').collect();
+            self.root.collect(&levels, true, &mut found);
+        }
+        found
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T> Default for TopicTree<T> {
+    fn default() -> Self {
+        TopicTree::new()
+    }
+}
+
+
+// Topic name that messages can be published to, validated on creation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TopicName(String);
+
+impl TopicName {
+    pub fn new<S: Into<String>>(topic: S) -> Result<Self, TopicError> {
+        let topic = topic.into();
+        try!(validate_topic(&topic));
+        Ok(TopicName(topic))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// Topic filter that can be subscribed to, validated on creation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TopicFilter(String);
+
+impl TopicFilter {
+    pub fn new<S: Into<String>>(filter: S) -> Result<Self, TopicError> {
+        let filter = filter.into();
+        try!(validate_filter(&filter));
+        Ok(TopicFilter(filter))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn matches(&self, topic: &str) -> bool {
+        topic_matches(&self.0, topic)
+    }
+
+    pub fn has_wildcards(&self) -> bool {
+        self.0.contains(&['+', '#'][..])
+    }
+}
+
+// Every topic name is also a filter matching only itself.
+impl From<TopicName> for TopicFilter {
+    fn from(topic: TopicName) -> Self {
+        TopicFilter(topic.0)
+    }
+}
+
+macro_rules! impl_topic_string {
+    ($t:ident) => {
+        impl Deref for $t {
+            type Target = str;
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+        impl AsRef<str> for $t {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+        impl FromStr for $t {
+            type Err = TopicError;
+            fn from_str(s: &str) -> Result<Self, TopicError> {
+                $t::new(s)
+            }
+        }
+        impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+    }
+}
+impl_topic_string!(TopicName);
+impl_topic_string!(TopicFilter);
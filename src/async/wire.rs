@@ -0,0 +1,57 @@
+/*
+ * The MIT License (MIT)
+ *
+ * Copyright (c) 2015 Andres Vahter (andres.vahter@gmail.com)
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+// Big endian integer helpers and id generation shared by the binary envelopes (chunks, RPC).
+
+pub fn put_u32(out: &mut Vec<u8>, v: u32) {
+    for i in (0..4).rev() {
+        out.push((v >> (i * 8)) as u8);
+    }
+}
+
+pub fn put_u64(out: &mut Vec<u8>, v: u64) {
+    put_u32(out, (v >> 32) as u32);
+    put_u32(out, v as u32);
+}
+
+pub fn get_u32(data: &[u8]) -> u32 {
+    data.iter().take(4).fold(0, |acc, &b| (acc << 8) | b as u32)
+}
+
+pub fn get_u64(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+static ID_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// Unique enough within a publisher: wall clock nanoseconds mixed with a process wide counter.
+pub fn new_id() -> u64 {
+    let t = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    let counter = ID_COUNTER.fetch_add(1, Ordering::SeqCst) as u64;
+    (t.as_secs() << 32 ^ (t.subsec_nanos() as u64) << 2) ^ counter.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
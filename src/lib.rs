@@ -26,6 +26,30 @@
 extern crate log;
 extern crate time;
 extern crate libc;
+#[cfg(any(feature = "serde", feature = "cbor", feature = "msgpack"))]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "cbor")]
+extern crate ciborium;
+#[cfg(feature = "msgpack")]
+extern crate rmp_serde;
+#[cfg(feature = "protobuf")]
+extern crate prost;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+#[cfg(feature = "zstd")]
+extern crate zstd;
+#[cfg(feature = "secure")]
+extern crate hmac;
+#[cfg(feature = "secure")]
+extern crate sha2;
+#[cfg(feature = "secure")]
+extern crate ed25519_dalek;
+#[cfg(feature = "secure")]
+extern crate chacha20poly1305;
+#[cfg(feature = "secure")]
+extern crate getrandom;
 
 #[allow(non_camel_case_types)]
 #[allow(non_snake_case)]
@@ -0,0 +1,126 @@
+extern crate mqtt;
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use common::message;
+use mqtt::async::{ChunkError, Reassembler, crc32, split, is_chunk, CHUNK_HEADER_LEN};
+
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[test]
+fn split_sizes() {
+    let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+    let chunks = split(&data, 256, 1).unwrap();
+    assert_eq!(chunks.len(), 4);
+    assert!(chunks.iter().all(|c| is_chunk(c)));
+    assert_eq!(chunks[3].len(), CHUNK_HEADER_LEN + 1000 - 3 * 256);
+    assert_eq!(split(&[], 256, 1).unwrap().len(), 1);
+}
+
+#[test]
+fn reassemble_out_of_order_with_duplicates() {
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i * 7) as u8).collect();
+    let mut chunks = split(&data, 999, 42).unwrap();
+    chunks.reverse();
+    let duplicate = chunks[3].clone();
+    chunks.insert(5, duplicate);
+
+    let mut reassembler = Reassembler::new(Duration::from_secs(10));
+    let last = chunks.pop().unwrap();
+    for chunk in chunks {
+        assert!(reassembler.feed(&message("blobs", chunk)).unwrap().is_none());
+    }
+    assert_eq!(reassembler.pending(), 1);
+    let transfer = reassembler.feed(&message("blobs", last)).unwrap().unwrap();
+    assert_eq!(transfer.id, 42);
+    assert_eq!(transfer.topic, "blobs");
+    assert_eq!(&transfer.payload[..], &data[..]);
+    assert_eq!(reassembler.pending(), 0);
+}
+
+#[test]
+fn transfers_are_kept_apart() {
+    let mut reassembler = Reassembler::new(Duration::from_secs(10));
+    let a = split(b"aaaa", 2, 1).unwrap();
+    let b = split(b"bbbb", 2, 2).unwrap();
+    assert!(reassembler.feed(&message("t", a[0].clone())).unwrap().is_none());
+    assert!(reassembler.feed(&message("t", b[1].clone())).unwrap().is_none());
+    assert!(reassembler.feed(&message("other", a[1].clone())).unwrap().is_none());
+    assert_eq!(&reassembler.feed(&message("t", a[1].clone())).unwrap().unwrap().payload[..], b"aaaa");
+    assert_eq!(&reassembler.feed(&message("t", b[0].clone())).unwrap().unwrap().payload[..], b"bbbb");
+    assert_eq!(reassembler.pending(), 1);
+}
+
+#[test]
+fn corrupted_transfer() {
+    let mut reassembler = Reassembler::new(Duration::from_secs(10));
+    let mut chunks = split(b"hello world", 4, 7).unwrap();
+    let last = chunks.len() - 1;
+    chunks[last][CHUNK_HEADER_LEN] ^= 0xff;
+    let mut result = Ok(None);
+    for chunk in chunks {
+        result = reassembler.feed(&message("t", chunk));
+    }
+    assert_eq!(result.err(), Some(ChunkError::Checksum(7)));
+
+    assert_eq!(reassembler.feed(&message("t", b"plain".to_vec())).err(), Some(ChunkError::NotChunk));
+
+    let first = split(b"hello world", 4, 8).unwrap();
+    let other = split(b"hello", 4, 8).unwrap();
+    assert!(reassembler.feed(&message("t", first[0].clone())).unwrap().is_none());
+    assert_eq!(reassembler.feed(&message("t", other[1].clone())).err(), Some(ChunkError::Inconsistent(8)));
+}
+
+#[test]
+fn incomplete_transfers_expire() {
+    let mut reassembler = Reassembler::new(Duration::from_millis(1));
+    let chunks = split(b"hello world", 4, 9).unwrap();
+    assert!(reassembler.feed(&message("t", chunks[0].clone())).unwrap().is_none());
+    thread::sleep(Duration::from_millis(5));
+    assert_eq!(reassembler.expire(), vec![("t".to_string(), 9)]);
+    assert_eq!(reassembler.pending(), 0);
+}
+
+#[test]
+fn zero_chunk_size_is_an_error() {
+    assert_eq!(split(b"data", 0, 1).err(), Some(ChunkError::ChunkSize));
+}
+
+#[test]
+fn oversized_transfer_is_dropped() {
+    let mut reassembler = Reassembler::new(Duration::from_secs(10));
+    reassembler.max_size = 8;
+    let chunks = split(&[1u8; 12], 4, 10).unwrap();
+    assert!(reassembler.feed(&message("t", chunks[0].clone())).unwrap().is_none());
+    assert!(reassembler.feed(&message("t", chunks[1].clone())).unwrap().is_none());
+    assert_eq!(reassembler.feed(&message("t", chunks[2].clone())).err(), Some(ChunkError::TooLarge(10)));
+    assert_eq!(reassembler.pending(), 0);
+
+    // declared chunk count alone can exceed the limit
+    let many = split(&[1u8; 9], 1, 11).unwrap();
+    assert_eq!(reassembler.feed(&message("t", many[0].clone())).err(), Some(ChunkError::TooLarge(11)));
+    assert_eq!(reassembler.pending(), 0);
+}
+
+#[test]
+fn pending_transfers_are_capped() {
+    let mut reassembler = Reassembler::new(Duration::from_secs(10));
+    reassembler.max_pending = 2;
+    for id in 0..2 {
+        let chunks = split(b"abcd", 2, id).unwrap();
+        assert!(reassembler.feed(&message("t", chunks[0].clone())).unwrap().is_none());
+    }
+    let third = split(b"abcd", 2, 2).unwrap();
+    assert_eq!(reassembler.feed(&message("t", third[0].clone())).err(), Some(ChunkError::TooManyTransfers(2)));
+
+    // chunks of transfers already pending are still accepted
+    let first = split(b"abcd", 2, 0).unwrap();
+    assert_eq!(&reassembler.feed(&message("t", first[1].clone())).unwrap().unwrap().payload[..], b"abcd");
+}
@@ -0,0 +1,91 @@
+#![cfg(any(feature = "serde", feature = "cbor", feature = "msgpack", feature = "protobuf"))]
+
+extern crate mqtt;
+extern crate serde;
+extern crate prost;
+
+mod common;
+
+use common::message;
+use mqtt::async::Codec;
+
+
+#[cfg(any(feature = "serde", feature = "cbor", feature = "msgpack"))]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Telemetry {
+    device      : String,
+    temperature : f64,
+    samples     : Vec<u32>,
+    online      : bool,
+}
+
+#[cfg(feature = "protobuf")]
+#[derive(Clone, PartialEq, prost::Message)]
+struct Reading {
+    #[prost(string, tag = "1")]
+    device: String,
+    #[prost(double, tag = "2")]
+    temperature: f64,
+    #[prost(uint32, repeated, tag = "3")]
+    samples: Vec<u32>,
+}
+
+#[cfg(any(feature = "serde", feature = "cbor", feature = "msgpack"))]
+fn telemetry() -> Telemetry {
+    Telemetry {
+        device      : "sensor-1".to_string(),
+        temperature : 21.5,
+        samples     : vec![1, 2, 3, 65536],
+        online      : true,
+    }
+}
+
+
+fn round_trip<T, C>(codec: &C, value: T) where T: PartialEq + ::std::fmt::Debug, C: Codec<T> {
+    let data = codec.encode(&value).unwrap();
+    assert_eq!(codec.decode(&data).unwrap(), value);
+    assert_eq!(message("telemetry", data).decode(codec).unwrap(), value);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_round_trip() {
+    use mqtt::async::{Json, PayloadError};
+    round_trip(&Json, telemetry());
+    assert_eq!(message("telemetry", b"{\"device\":1}".to_vec()).json::<Telemetry>().is_err(), true);
+    match Codec::<Telemetry>::decode(&Json, b"not json") {
+        Err(PayloadError::Decode(_)) => {},
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_round_trip() {
+    use mqtt::async::Cbor;
+    round_trip(&Cbor, telemetry());
+    round_trip(&Cbor, vec![0u8, 255]);
+    assert!(Codec::<Telemetry>::decode(&Cbor, &[0xff, 0x00]).is_err());
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_round_trip() {
+    use mqtt::async::MessagePack;
+    round_trip(&MessagePack, telemetry());
+    round_trip(&MessagePack, "text".to_string());
+    assert!(Codec::<Telemetry>::decode(&MessagePack, &[0xc1]).is_err());
+}
+
+#[cfg(feature = "protobuf")]
+#[test]
+fn protobuf_round_trip() {
+    use mqtt::async::Protobuf;
+    round_trip(&Protobuf, Reading {
+        device      : "sensor-1".to_string(),
+        temperature : 21.5,
+        samples     : vec![1, 2, 3, 65536],
+    });
+    round_trip(&Protobuf, Reading::default());
+    assert!(Codec::<Reading>::decode(&Protobuf, &[0x0a, 0x05, 0x61]).is_err());
+}
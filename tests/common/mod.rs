@@ -0,0 +1,12 @@
+use mqtt::async::{Message, Payload, Qos, TopicName};
+
+
+// QoS 1 message with msgid 1, as it would arrive from the broker.
+pub fn message<P: Into<Payload>>(topic: &str, payload: P) -> Message {
+    let mut msg = Message::builder(TopicName::new(topic).unwrap())
+        .payload(payload)
+        .qos(Qos::AtLeastOnce)
+        .build();
+    msg.msgid = 1;
+    msg
+}
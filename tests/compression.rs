@@ -0,0 +1,61 @@
+#![cfg(any(feature = "gzip", feature = "zstd"))]
+
+extern crate mqtt;
+
+use mqtt::async::{Algorithm, Compression, MAX_DECOMPRESSED_SIZE, Payload, decompress, is_compressed};
+
+
+fn algorithms() -> Vec<Algorithm> {
+    let mut algorithms = Vec::new();
+    #[cfg(feature = "gzip")]
+    algorithms.push(Algorithm::Gzip);
+    #[cfg(feature = "zstd")]
+    algorithms.push(Algorithm::Zstd);
+    algorithms
+}
+
+#[test]
+fn round_trip_above_threshold() {
+    let data = "{\"temperature\": 21.5, \"humidity\": 40}".repeat(100).into_bytes();
+    for algorithm in algorithms() {
+        let compressed = Compression::new(algorithm).compress(&data).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < data.len());
+        assert_eq!(&decompress(&compressed, MAX_DECOMPRESSED_SIZE).unwrap()[..], &data[..]);
+    }
+}
+
+#[test]
+fn small_payloads_are_sent_as_is() {
+    let data = b"{\"temperature\": 21.5}".to_vec();
+    for algorithm in algorithms() {
+        let compressed = Compression::new(algorithm).compress(&data).unwrap();
+        assert!(!is_compressed(&compressed));
+        assert_eq!(&compressed[..], &data[..]);
+    }
+}
+
+#[test]
+fn uncompressed_payload_passes_through() {
+    let payload = Payload::from("plain text");
+    assert_eq!(decompress(&payload, MAX_DECOMPRESSED_SIZE).unwrap(), payload);
+    assert_eq!(decompress(&Payload::new(), MAX_DECOMPRESSED_SIZE).unwrap(), Payload::new());
+}
+
+#[test]
+fn unknown_algorithm_is_an_error() {
+    assert!(decompress(&Payload::from(&b"\0MQZ\x7fdata"[..]), MAX_DECOMPRESSED_SIZE).is_err());
+}
+
+#[test]
+fn output_above_max_size_is_an_error() {
+    let data = vec![0u8; 1024 * 1024];
+    for algorithm in algorithms() {
+        let mut compression = Compression::new(algorithm);
+        compression.max_size = data.len() - 1;
+        let compressed = compression.compress(&data).unwrap();
+        assert!(compressed.len() < 16 * 1024);
+        assert!(compression.decompress(&compressed).is_err());
+        assert_eq!(decompress(&compressed, data.len()).unwrap().len(), data.len());
+    }
+}
@@ -0,0 +1,60 @@
+extern crate mqtt;
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use common::message;
+use mqtt::async::{Deduplicator, DedupStats, Message, Qos};
+
+fn reading(msgid: i32, payload: &str) -> Message {
+    let mut msg = message("telemetry", payload);
+    msg.msgid = msgid;
+    msg
+}
+
+#[test]
+fn suppresses_redelivery() {
+    let dedup = Deduplicator::new(Duration::from_secs(60));
+    assert!(dedup.check(&reading(1, "a")));
+    let mut redelivered = reading(1, "a");
+    redelivered.duplicate = true;
+    assert!(!dedup.check(&redelivered));
+    // same id with other content and other id with same content are different messages
+    assert!(dedup.check(&reading(1, "b")));
+    assert!(dedup.check(&reading(2, "a")));
+    assert_eq!(dedup.stats(), DedupStats { passed: 3, suppressed: 1 });
+}
+
+#[test]
+fn window_expires() {
+    let dedup = Deduplicator::new(Duration::from_millis(1));
+    assert!(dedup.check(&reading(1, "a")));
+    thread::sleep(Duration::from_millis(5));
+    let mut redelivered = reading(1, "a");
+    redelivered.duplicate = true;
+    assert!(dedup.check(&redelivered));
+    assert_eq!(dedup.clone().stats().suppressed, 0);
+}
+
+#[test]
+fn qos0_messages_pass() {
+    let dedup = Deduplicator::new(Duration::from_secs(60));
+    let mut msg = reading(0, "21.5");
+    msg.qos = Qos::FireAndForget;
+    assert!(dedup.check(&msg));
+    assert!(dedup.check(&msg));
+    assert_eq!(dedup.stats(), DedupStats { passed: 2, suppressed: 0 });
+}
+
+#[test]
+fn reused_msgid_without_dup_flag_passes() {
+    let dedup = Deduplicator::new(Duration::from_secs(60));
+    assert!(dedup.check(&reading(1, "ON")));
+    assert!(dedup.check(&reading(1, "ON")));
+    // redelivery of the second one is still caught
+    let mut redelivered = reading(1, "ON");
+    redelivered.duplicate = true;
+    assert!(!dedup.check(&redelivered));
+    assert_eq!(dedup.stats(), DedupStats { passed: 2, suppressed: 1 });
+}
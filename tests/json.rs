@@ -0,0 +1,59 @@
+#![cfg(feature = "serde")]
+
+extern crate mqtt;
+extern crate serde;
+
+mod common;
+
+use std::collections::BTreeMap;
+use common::message;
+use mqtt::async::{AsyncClient, MqttError, PayloadError, Qos, TopicName};
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Reading {
+    sensor : String,
+    value  : f64,
+}
+
+
+#[test]
+fn json_payload() {
+    let reading: Reading = message("sensors/t", r#"{"sensor": "t1", "value": 21.5}"#).json().unwrap();
+    assert_eq!(reading, Reading { sensor: "t1".to_string(), value: 21.5 });
+
+    let map: BTreeMap<String, f64> = message("sensors/t", r#"{"a": 1.0}"#).json().unwrap();
+    assert_eq!(map["a"], 1.0);
+}
+
+#[test]
+fn json_decode_error() {
+    match message("sensors/t", r#"{"sensor": "t1"}"#).json::<Reading>() {
+        Err(PayloadError::Decode(_)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(message("sensors/t", "").json::<Reading>().is_err());
+}
+
+#[derive(Debug, Deserialize)]
+struct Command {
+    action : String,
+}
+
+#[derive(Serialize)]
+struct Status<'a> {
+    state : &'a str,
+}
+
+#[test]
+fn one_way_types() {
+    let command: Command = message("sensors/t", r#"{"action": "reboot"}"#).json().unwrap();
+    assert_eq!(command.action, "reboot");
+
+    // serialize-only and unsized values can be sent, checked at compile time only
+    let _ = |client: &mut AsyncClient, topic: &TopicName| -> Result<(), MqttError> {
+        try!(client.send_json(&Status { state: "ok" }, topic, Qos::AtLeastOnce, false));
+        client.send_json(&[1, 2, 3][..], topic, Qos::AtLeastOnce, false)
+    };
+}
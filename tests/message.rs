@@ -0,0 +1,47 @@
+extern crate mqtt;
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use mqtt::async::{Message, MessageBuilder, Payload, Qos, QosError, TopicName};
+
+
+#[test]
+fn qos_from_int() {
+    assert_eq!(Qos::from_int(0), Ok(Qos::FireAndForget));
+    assert_eq!(Qos::from_int(1), Ok(Qos::AtLeastOnce));
+    assert_eq!(Qos::try_from(2), Ok(Qos::OnceAndOneOnly));
+    assert_eq!(Qos::from_int(3), Err(QosError::Invalid(3)));
+    assert_eq!(Qos::try_from(-1), Err(QosError::Invalid(-1)));
+
+    let set: HashSet<Qos> = [Qos::AtLeastOnce, Qos::AtLeastOnce, Qos::FireAndForget].iter().cloned().collect();
+    assert_eq!(set.len(), 2);
+}
+
+#[test]
+fn builder_defaults() {
+    let msg = MessageBuilder::new(TopicName::new("a/b").unwrap()).build();
+    assert_eq!(msg.topic, "a/b");
+    assert!(msg.payload.is_empty());
+    assert_eq!(msg.qos, Qos::FireAndForget);
+    assert!(!msg.retained);
+    assert!(!msg.duplicate);
+    assert!(msg.delivery.is_none());
+}
+
+#[test]
+fn builder_roundtrip() {
+    let msg = Message::builder(TopicName::new("a/b").unwrap())
+        .payload("hello")
+        .qos(Qos::AtLeastOnce)
+        .retained(true)
+        .build();
+    assert_eq!(msg.payload, Payload::from("hello"));
+    assert_eq!(msg.qos, Qos::AtLeastOnce);
+    assert!(msg.retained);
+
+    let forwarded = msg.to_builder().unwrap().topic(TopicName::new("c").unwrap()).build();
+    assert_eq!(forwarded.topic, "c");
+    assert_eq!(forwarded.payload, msg.payload);
+    assert_eq!(forwarded.qos, Qos::AtLeastOnce);
+    assert!(forwarded.retained);
+}
@@ -0,0 +1,38 @@
+extern crate mqtt;
+
+use mqtt::async::BrokerStats;
+
+
+#[test]
+fn mosquitto_stats() {
+    let mut stats = BrokerStats::new();
+    stats.update("$SYS/broker/version", "mosquitto version 1.4.8");
+    stats.update("$SYS/broker/uptime", "3600 seconds");
+    stats.update("$SYS/broker/clients/connected", "5");
+    stats.update("$SYS/broker/messages/received", "100");
+    stats.update("$SYS/broker/messages/sent", "200");
+    stats.update("$SYS/broker/bytes/received", "1000");
+    stats.update("$SYS/broker/bytes/sent", "2000");
+    stats.update("$SYS/broker/load/messages/received/1min", "1.5");
+
+    assert_eq!(stats.version, Some("mosquitto version 1.4.8".to_string()));
+    assert_eq!(stats.uptime_secs, Some(3600));
+    assert_eq!(stats.clients_connected, Some(5));
+    assert_eq!(stats.messages_received, Some(100));
+    assert_eq!(stats.messages_sent, Some(200));
+    assert_eq!(stats.bytes_received, Some(1000));
+    assert_eq!(stats.bytes_sent, Some(2000));
+    assert_eq!(stats.raw["$SYS/broker/load/messages/received/1min"], "1.5");
+}
+
+#[test]
+fn changes_are_reported_once() {
+    let mut stats = BrokerStats::new();
+    let change = stats.update("$SYS/broker/clients/connected", "5").unwrap();
+    assert_eq!(change.previous, None);
+    assert_eq!(change.current, "5");
+    assert!(stats.update("$SYS/broker/clients/connected", "5").is_none());
+    let change = stats.update("$SYS/broker/clients/connected", "6").unwrap();
+    assert_eq!(change.previous, Some("5".to_string()));
+    assert_eq!(stats.clients_connected, Some(6));
+}
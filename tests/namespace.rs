@@ -0,0 +1,46 @@
+extern crate mqtt;
+
+mod common;
+
+use common::message;
+use mqtt::async::{Namespace, TopicError, TopicFilter, TopicName};
+
+
+fn stripped(namespace: &Namespace, topic: &str) -> Option<String> {
+    namespace.strip(message(topic, "x")).map(|msg| msg.topic)
+}
+
+#[test]
+fn prefixes_topics_and_filters() {
+    let namespace = Namespace::new("tenant/a").unwrap();
+    assert_eq!(namespace.topic(&TopicName::new("sensors/t").unwrap()).unwrap().as_str(), "tenant/a/sensors/t");
+    assert_eq!(namespace.filter(&TopicFilter::new("sensors/+").unwrap()).unwrap().as_str(), "tenant/a/sensors/+");
+    assert_eq!(namespace.filter(&TopicFilter::new("#").unwrap()).unwrap().as_str(), "tenant/a/#");
+    assert_eq!(Namespace::new("tenant/+"), Err(TopicError::WildcardInName));
+    assert_eq!(Namespace::new("/"), Err(TopicError::Empty));
+}
+
+#[test]
+fn trailing_slashes_are_ignored() {
+    let namespace = Namespace::new("tenant/a//").unwrap();
+    assert_eq!(namespace.prefix().as_str(), "tenant/a");
+    assert_eq!(namespace.topic(&TopicName::new("x").unwrap()).unwrap().as_str(), "tenant/a/x");
+    assert_eq!(stripped(&namespace, "tenant/a/x"), Some("x".to_string()));
+}
+
+#[test]
+fn strips_only_inside_namespace() {
+    let namespace = Namespace::new("tenant/a").unwrap();
+    assert_eq!(stripped(&namespace, "tenant/a/x/y"), Some("x/y".to_string()));
+    assert_eq!(stripped(&namespace, "tenant/ab/x"), None);
+    assert_eq!(stripped(&namespace, "tenant/b/x"), None);
+    assert_eq!(stripped(&namespace, "other"), None);
+}
+
+#[test]
+fn relative_hash_receives_namespace_itself() {
+    let namespace = Namespace::new("tenant/a").unwrap();
+    let filter = namespace.filter(&TopicFilter::new("#").unwrap()).unwrap();
+    assert!(filter.matches("tenant/a"));
+    assert_eq!(stripped(&namespace, "tenant/a"), Some(String::new()));
+}
@@ -0,0 +1,121 @@
+extern crate mqtt;
+
+use mqtt::async::{CommandError, DropReason, MqttError, OfflineError, OfflineEvent, OfflineQueue, OverflowPolicy, Qos, TopicName};
+
+
+fn push(queue: &OfflineQueue, payload: &str) -> Result<u64, MqttError> {
+    queue.push(payload.as_bytes(), &TopicName::new("a/b").unwrap(), Qos::AtLeastOnce, false)
+}
+
+fn payloads(queue: &OfflineQueue) -> Vec<String> {
+    let mut sent = Vec::new();
+    queue.flush_with(|msg| { sent.push(String::from_utf8(msg.payload.to_vec()).unwrap()); Ok(()) }).unwrap();
+    sent
+}
+
+#[test]
+fn flushes_in_order() {
+    let queue = OfflineQueue::new(10, OverflowPolicy::Reject);
+    for p in ["1", "2", "3"].iter() {
+        push(&queue, p).unwrap();
+    }
+    assert_eq!(queue.len(), 3);
+    assert_eq!(payloads(&queue), vec!["1", "2", "3"]);
+    assert!(queue.is_empty());
+    let delivered: Vec<u64> = queue.events().into_iter().map(|event| match event {
+        OfflineEvent::Delivered(id, msg) => { assert_eq!(msg.topic, "a/b"); id }
+        other => panic!("unexpected {:?}", other),
+    }).collect();
+    assert_eq!(delivered, vec![0, 1, 2]);
+    assert!(queue.events().is_empty());
+}
+
+#[test]
+fn failed_send_stays_queued() {
+    let queue = OfflineQueue::new(10, OverflowPolicy::Reject);
+    push(&queue, "1").unwrap();
+    push(&queue, "2").unwrap();
+    let mut attempts = 0;
+    let result = queue.flush_with(|_| { attempts += 1; if attempts == 2 { Err(MqttError::Create(-3)) } else { Ok(()) } });
+    assert!(result.is_err());
+    assert_eq!(queue.len(), 1);
+    assert_eq!(payloads(&queue), vec!["2"]);
+}
+
+#[test]
+fn overflow_policies() {
+    let reject = OfflineQueue::new(1, OverflowPolicy::Reject);
+    push(&reject, "1").unwrap();
+    match push(&reject, "2") {
+        Err(MqttError::Offline(OfflineError::QueueFull(1))) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let newest = OfflineQueue::new(1, OverflowPolicy::DropNewest);
+    push(&newest, "1").unwrap();
+    assert_eq!(push(&newest, "2").unwrap(), 1);
+    match newest.events()[..] {
+        [OfflineEvent::Dropped(1, _, DropReason::Overflow)] => (),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(payloads(&newest), vec!["1"]);
+
+    let oldest = OfflineQueue::new(1, OverflowPolicy::DropOldest);
+    push(&oldest, "1").unwrap();
+    push(&oldest, "2").unwrap();
+    match oldest.events()[..] {
+        [OfflineEvent::Dropped(0, _, DropReason::Overflow)] => (),
+        ref other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(payloads(&oldest), vec!["2"]);
+
+    push(&oldest, "3").unwrap();
+    assert_eq!(oldest.clear(), 1);
+    match oldest.events()[..] {
+        [OfflineEvent::Delivered(1, _), OfflineEvent::Dropped(2, _, DropReason::Cleared)] => (),
+        ref other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn permanent_failure_is_dropped() {
+    let queue = OfflineQueue::new(10, OverflowPolicy::Reject);
+    push(&queue, "1").unwrap();
+    push(&queue, "2").unwrap();
+    // bad UTF-8 is never accepted, the next message is sent
+    let mut sent = Vec::new();
+    let result = queue.flush_with(|msg| {
+        if &msg.payload[..] == b"1" {
+            Err(MqttError::Send(CommandError::ReturnCode(-5)))
+        } else {
+            sent.push(msg.payload.to_vec());
+            Ok(())
+        }
+    });
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(sent, vec![b"2".to_vec()]);
+    assert!(queue.is_empty());
+    match queue.events()[..] {
+        [OfflineEvent::Dropped(0, _, DropReason::Failed), OfflineEvent::Delivered(1, _)] => (),
+        ref other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn events_are_capped() {
+    let queue = OfflineQueue::new(10, OverflowPolicy::Reject);
+    queue.set_max_events(2);
+    for p in ["1", "2", "3"].iter() {
+        push(&queue, p).unwrap();
+    }
+    payloads(&queue);
+    match queue.events()[..] {
+        [OfflineEvent::Delivered(1, _), OfflineEvent::Delivered(2, _)] => (),
+        ref other => panic!("unexpected {:?}", other),
+    }
+
+    queue.set_max_events(0);
+    push(&queue, "4").unwrap();
+    payloads(&queue);
+    assert!(queue.events().is_empty());
+}
@@ -0,0 +1,55 @@
+extern crate mqtt;
+
+use std::thread;
+use std::time::Duration;
+use mqtt::async::{PeerStatus, PeerTracker, Presence, PresenceChange, Qos, TopicName};
+
+
+#[test]
+fn will_is_retained_offline_status() {
+    let presence = Presence::new(TopicName::new("devices/d1/status").unwrap()).qos(Qos::OnceAndOneOnly);
+    let will = presence.will();
+    assert_eq!(will.topic.as_str(), "devices/d1/status");
+    assert_eq!(will.payload, "offline");
+    assert_eq!(will.qos, Qos::OnceAndOneOnly);
+    assert!(will.retained);
+
+    let custom = Presence::new(TopicName::new("s").unwrap()).payloads("up", "down");
+    assert_eq!(custom.will().payload, "down");
+}
+
+#[test]
+fn tracks_status_changes() {
+    let mut tracker = PeerTracker::new(None);
+    let change = tracker.update("devices/d1/status", b"online").unwrap();
+    assert_eq!(change, PresenceChange { topic: "devices/d1/status".to_string(), previous: None, current: Some(PeerStatus::Online) });
+    // heartbeat refresh is not a change
+    assert!(tracker.update("devices/d1/status", b"online").is_none());
+    assert!(tracker.update("devices/d1/status", b"garbage").is_none());
+    tracker.update("devices/d2/status", b"online");
+    assert_eq!(tracker.online(), vec!["devices/d1/status", "devices/d2/status"]);
+
+    let change = tracker.update("devices/d1/status", b"offline").unwrap();
+    assert_eq!(change.previous, Some(PeerStatus::Online));
+    assert_eq!(change.current, Some(PeerStatus::Offline));
+    assert_eq!(tracker.status("devices/d1/status"), Some(PeerStatus::Offline));
+
+    // cleared retained status removes the peer
+    let change = tracker.update("devices/d1/status", b"").unwrap();
+    assert_eq!(change.current, None);
+    assert_eq!(tracker.status("devices/d1/status"), None);
+    assert_eq!(tracker.peers().len(), 1);
+}
+
+#[test]
+fn silent_peers_expire() {
+    let mut tracker = PeerTracker::new(Some(Duration::from_millis(5))).payloads("1", "0");
+    tracker.update("a", b"1");
+    assert!(tracker.expire().is_empty());
+    thread::sleep(Duration::from_millis(10));
+    let changes = tracker.expire();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].current, Some(PeerStatus::Offline));
+    assert_eq!(tracker.status("a"), Some(PeerStatus::Offline));
+    assert!(tracker.expire().is_empty());
+}
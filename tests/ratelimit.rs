@@ -0,0 +1,138 @@
+extern crate mqtt;
+
+use std::thread;
+use std::time::{Duration, Instant};
+use mqtt::async::{CommandError, Limit, MqttError, OfflineEvent, OfflineQueue, OverflowPolicy, Qos, RateLimitError, RateLimiter, RatePolicy, TopicFilter, TopicName};
+
+
+fn topic(name: &str) -> TopicName {
+    TopicName::new(name).unwrap()
+}
+
+#[test]
+fn fail_fast_per_filter() {
+    let limiter = RateLimiter::new(RatePolicy::FailFast);
+    limiter.add_limit(&TopicFilter::new("sensors/#").unwrap(), Limit::new(1.0, 2).unwrap());
+    let mut sent = 0;
+    for _ in 0..2 {
+        limiter.send_with(b"x", &topic("sensors/a"), Qos::FireAndForget, false, |_, _, _, _| { sent += 1; Ok(()) }).unwrap();
+    }
+    match limiter.send_with(b"x", &topic("sensors/a"), Qos::FireAndForget, false, |_, _, _, _| { sent += 1; Ok(()) }) {
+        Err(MqttError::RateLimit(RateLimitError::Exceeded(ref t, wait))) => {
+            assert_eq!(t, "sensors/a");
+            assert!(wait > Duration::from_millis(0));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(sent, 2);
+
+    // other topics are not limited
+    assert!(limiter.send_with(b"x", &topic("other"), Qos::FireAndForget, false, |_, _, _, _| Ok(())).is_ok());
+
+    let stats = limiter.stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].filter, Some("sensors/#".to_string()));
+    assert_eq!(stats[0].rate, 2.0);
+    assert_eq!(stats[0].limited, 1);
+}
+
+#[test]
+fn global_limit_blocks() {
+    let limiter = RateLimiter::new(RatePolicy::Block);
+    limiter.set_global(Some(Limit::new(100.0, 1).unwrap()));
+    let start = Instant::now();
+    let mut count = 0;
+    for _ in 0..3 {
+        limiter.send_with(b"x", &topic("a"), Qos::FireAndForget, false, |_, _, _, _| { count += 1; Ok(()) }).unwrap();
+    }
+    assert_eq!(count, 3);
+    assert!(start.elapsed() >= Duration::from_millis(15));
+    assert_eq!(limiter.stats()[0].filter, None);
+    assert_eq!(limiter.stats()[0].limited, 2);
+}
+
+#[test]
+fn queue_keeps_order() {
+    let limiter = RateLimiter::new(RatePolicy::Queue(2));
+    limiter.set_global(Some(Limit::new(200.0, 1).unwrap()));
+    let mut sent = Vec::new();
+    for payload in [b"1", b"2", b"3"].iter() {
+        limiter.send_with(&payload[..], &topic("a"), Qos::FireAndForget, false, |d, _, _, _| { sent.push(d.to_vec()); Ok(()) }).unwrap();
+    }
+    assert_eq!(limiter.queued(), 2);
+    match limiter.send_with(b"4", &topic("a"), Qos::FireAndForget, false, |_, _, _, _| Ok(())) {
+        Err(MqttError::RateLimit(RateLimitError::QueueFull(_))) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    let flushed = limiter.flush_with(true, |d, _, _, _| { sent.push(d.to_vec()); Ok(()) }).unwrap();
+    assert_eq!(flushed, 2);
+    assert_eq!(sent, vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec()]);
+    assert_eq!(limiter.queued(), 0);
+}
+
+#[test]
+fn failed_queued_message_stays_queued() {
+    let limiter = RateLimiter::new(RatePolicy::Queue(4));
+    limiter.set_global(Some(Limit::new(1000.0, 1).unwrap()));
+    let mut sent = Vec::new();
+    limiter.send_with(b"first", &topic("a"), Qos::FireAndForget, false, |d, _, _, _| { sent.push(d.to_vec()); Ok(()) }).unwrap();
+    limiter.send_with(b"second", &topic("a"), Qos::FireAndForget, false, |d, _, _, _| { sent.push(d.to_vec()); Ok(()) }).unwrap();
+    assert_eq!(limiter.queued(), 1);
+    thread::sleep(Duration::from_millis(5));
+
+    // sending "second" from the queue fails, "third" is still accepted and queued behind it
+    let result = limiter.send_with(b"third", &topic("a"), Qos::FireAndForget, false, |d, _, _, _| {
+        if d == b"second" {
+            Err(MqttError::Send(CommandError::ReturnCode(-3)))
+        } else {
+            sent.push(d.to_vec());
+            Ok(())
+        }
+    });
+    assert!(result.is_ok());
+    assert_eq!(limiter.queued(), 2);
+
+    match limiter.flush_with(true, |_, _, _, _| Err(MqttError::Send(CommandError::ReturnCode(-3)))) {
+        Err(MqttError::Send(CommandError::ReturnCode(-3))) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(limiter.queued(), 2);
+
+    let flushed = limiter.flush_with(true, |d, _, _, _| { sent.push(d.to_vec()); Ok(()) }).unwrap();
+    assert_eq!(flushed, 2);
+    assert_eq!(sent, vec![b"first".to_vec(), b"second".to_vec(), b"third".to_vec()]);
+}
+
+#[test]
+fn offline_flush_is_not_queued() {
+    let limiter = RateLimiter::new(RatePolicy::Queue(4));
+    limiter.set_global(Some(Limit::new(0.001, 1).unwrap()));
+    let offline = OfflineQueue::new(4, OverflowPolicy::DropNewest);
+    for payload in &["1", "2"] {
+        offline.push(payload.as_bytes(), &topic("a"), Qos::AtLeastOnce, false).unwrap();
+    }
+
+    let mut sent = Vec::new();
+    let result = offline.flush_with(|msg| {
+        let topic = TopicName::new(msg.topic.as_str()).unwrap();
+        limiter.send_unqueued_with(&msg.payload, &topic, msg.qos, msg.retained, |d, _, _, _| { sent.push(d.to_vec()); Ok(()) })
+    });
+    match result {
+        Err(MqttError::RateLimit(RateLimitError::Exceeded(..))) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    // second message stays in the offline queue and is not reported as delivered
+    assert_eq!(sent, vec![b"1".to_vec()]);
+    assert_eq!(limiter.queued(), 0);
+    assert_eq!(offline.len(), 1);
+    let delivered = offline.events().into_iter().filter(|e| match *e { OfflineEvent::Delivered(..) => true, _ => false }).count();
+    assert_eq!(delivered, 1);
+}
+
+#[test]
+fn invalid_limit() {
+    assert_eq!(Limit::new(0.0, 1), Err(RateLimitError::InvalidLimit));
+    assert_eq!(Limit::new(-1.0, 1), Err(RateLimitError::InvalidLimit));
+    assert!(Limit::new(f64::NAN, 1).is_err());
+    assert_eq!(Limit::new(10.0, 0).unwrap().burst(), 1);
+}
@@ -0,0 +1,65 @@
+extern crate mqtt;
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use common::message;
+use mqtt::async::{Router, TopicFilter};
+
+
+#[test]
+fn dispatch_to_matching_handlers() {
+    let router = Router::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+
+    let s = seen.clone();
+    router.add(&TopicFilter::new("sensors/+/temp").unwrap(), move |msg| s.lock().unwrap().push(format!("temp {}", msg.topic)));
+    let s = seen.clone();
+    router.add(&TopicFilter::new("sensors/#").unwrap(), move |msg| s.lock().unwrap().push(format!("all {}", msg.topic)));
+    assert_eq!(router.len(), 2);
+
+    assert_eq!(router.dispatch(&message("sensors/1/temp", "")), 2);
+    assert_eq!(router.dispatch(&message("sensors/1/humidity", "")), 1);
+    assert_eq!(router.dispatch(&message("other", "")), 0);
+
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort();
+    assert_eq!(seen, vec!["all sensors/1/humidity", "all sensors/1/temp", "temp sensors/1/temp"]);
+}
+
+#[test]
+fn remove_route() {
+    let router = Router::new();
+    let count = Arc::new(Mutex::new(0));
+
+    let c = count.clone();
+    let id = router.add(&TopicFilter::new("a").unwrap(), move |_| *c.lock().unwrap() += 1);
+    assert_eq!(router.dispatch(&message("a", "")), 1);
+    assert!(router.remove(id));
+    assert!(!router.remove(id));
+    assert!(router.is_empty());
+    assert_eq!(router.dispatch(&message("a", "")), 0);
+    assert_eq!(*count.lock().unwrap(), 1);
+}
+
+#[test]
+fn clones_share_routes() {
+    let router = Router::new();
+    let other = router.clone();
+    other.add(&TopicFilter::new("#").unwrap(), |_| {});
+    assert_eq!(router.dispatch(&message("x/y", "")), 1);
+}
+
+#[test]
+fn panicking_handler_does_not_break_router() {
+    let router = Router::new();
+    let count = Arc::new(Mutex::new(0));
+    router.add(&TopicFilter::new("boom").unwrap(), |_| panic!("handler"));
+    let c = count.clone();
+    router.add(&TopicFilter::new("#").unwrap(), move |_| *c.lock().unwrap() += 1);
+
+    assert_eq!(router.dispatch(&message("boom", "")), 2);
+    assert_eq!(router.dispatch(&message("other", "")), 1);
+    assert_eq!(router.len(), 2);
+    assert_eq!(*count.lock().unwrap(), 2);
+}
@@ -0,0 +1,53 @@
+extern crate mqtt;
+
+use std::thread;
+use mqtt::async::{LocalBroker, MessageBuilder, MqttError, Qos, Requester, Responder, RpcError, TopicFilter, TopicName};
+
+
+#[test]
+fn loopback_request_response() {
+    let mut broker = LocalBroker::new();
+    let mut responder = Responder::new(&mut broker, &TopicFilter::new("devices/+/command").unwrap(), Qos::AtLeastOnce).unwrap();
+
+    let mut server = broker.clone();
+    let handle = thread::spawn(move || {
+        let mut answered = 0;
+        while answered < 3 {
+            answered += responder.poll(&mut server, 50, |request| {
+                let mut reply = request.topic.clone().into_bytes();
+                reply.push(b':');
+                reply.extend_from_slice(&request.payload);
+                reply
+            }).unwrap();
+        }
+    });
+
+    let mut requester = Requester::new(&mut broker, TopicName::new("replies/client1").unwrap(), Qos::AtLeastOnce).unwrap();
+    for i in 0..3 {
+        let topic = TopicName::new(format!("devices/{}/command", i)).unwrap();
+        let response = requester.request(&mut broker, &topic, "reboot", 1000).unwrap();
+        assert_eq!(&response.payload[..], format!("devices/{}/command:reboot", i).as_bytes());
+        assert_eq!(response.topic, "replies/client1");
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn request_times_out_without_responder() {
+    let mut broker = LocalBroker::new();
+    let mut requester = Requester::new(&mut broker, TopicName::new("replies/client1").unwrap(), Qos::FireAndForget).unwrap();
+    match requester.request(&mut broker, &TopicName::new("nobody/listens").unwrap(), "ping", 20) {
+        Err(MqttError::Rpc(RpcError::Timeout(_))) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn non_envelope_messages_are_skipped() {
+    let mut broker = LocalBroker::new();
+    let mut responder = Responder::new(&mut broker, &TopicFilter::new("svc").unwrap(), Qos::FireAndForget).unwrap();
+    let plain = MessageBuilder::new(TopicName::new("svc").unwrap()).payload("not a request").build();
+    broker.publish(&plain).unwrap();
+    let answered = responder.poll(&mut broker, 10, |_| Vec::new()).unwrap();
+    assert_eq!(answered, 0);
+}
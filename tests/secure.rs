@@ -0,0 +1,124 @@
+#![cfg(feature = "secure")]
+
+extern crate mqtt;
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+use common::message;
+use mqtt::async::{MqttError, SecureLayer, TopicKeys, SignatureKey, SignatureKind, SigningKey, SecurityError};
+
+
+fn hmac_layer(encryption: Option<[u8; 32]>) -> SecureLayer {
+    let mut layer = SecureLayer::new();
+    assert!(layer.add_keys("devices/#", TopicKeys {
+        signature  : SignatureKey::Hmac(b"shared secret".to_vec()),
+        encryption : encryption,
+    }));
+    layer
+}
+
+#[test]
+fn hmac_signed() {
+    let sender = hmac_layer(None);
+    let mut receiver = hmac_layer(None);
+
+    let sealed = sender.seal("devices/1", b"hello").unwrap();
+    let verified = receiver.open(&message("devices/1", sealed)).unwrap();
+    assert_eq!(&verified.message.payload[..], b"hello");
+    assert_eq!(verified.verification.signature, SignatureKind::Hmac);
+    assert!(!verified.verification.encrypted);
+}
+
+#[test]
+fn encrypted() {
+    let key = [7u8; 32];
+    let sender = hmac_layer(Some(key));
+    let mut receiver = hmac_layer(Some(key));
+
+    let sealed = sender.seal("devices/1", b"secret reading").unwrap();
+    assert!(!sealed.windows(6).any(|w| w == b"secret"));
+    let verified = receiver.open(&message("devices/1", sealed)).unwrap();
+    assert_eq!(&verified.message.payload[..], b"secret reading");
+    assert!(verified.verification.encrypted);
+
+    let mut wrong_key = hmac_layer(Some([8u8; 32]));
+    let sealed = sender.seal("devices/1", b"secret reading").unwrap();
+    assert_eq!(wrong_key.open(&message("devices/1", sealed)).err(), Some(SecurityError::Decrypt));
+}
+
+#[test]
+fn ed25519_signed() {
+    let signing = SigningKey::from_bytes(&[3u8; 32]);
+    let mut sender = SecureLayer::new();
+    sender.add_keys("devices/+/telemetry", TopicKeys {
+        signature  : SignatureKey::Ed25519(signing.clone()),
+        encryption : None,
+    });
+    let mut receiver = SecureLayer::new();
+    receiver.add_keys("devices/#", TopicKeys {
+        signature  : SignatureKey::Ed25519Verify(signing.verifying_key()),
+        encryption : None,
+    });
+
+    let sealed = sender.seal("devices/1/telemetry", b"21.5").unwrap();
+    let verified = receiver.open(&message("devices/1/telemetry", sealed)).unwrap();
+    assert_eq!(&verified.message.payload[..], b"21.5");
+    assert_eq!(verified.verification.signature, SignatureKind::Ed25519);
+
+    // public key can not sign
+    assert_eq!(receiver.seal("devices/1/telemetry", b"x").err(), Some(SecurityError::CanNotSign("devices/1/telemetry".to_string())));
+}
+
+#[test]
+fn tampering_is_detected() {
+    let sender = hmac_layer(None);
+    let mut receiver = hmac_layer(None);
+
+    let mut sealed = sender.seal("devices/1", b"hello").unwrap();
+    let body = sealed.len() - 33;
+    sealed[body] ^= 1;
+    assert_eq!(receiver.open(&message("devices/1", sealed)).err(), Some(SecurityError::BadSignature));
+
+    // signature is bound to topic
+    let sealed = sender.seal("devices/1", b"hello").unwrap();
+    assert_eq!(receiver.open(&message("devices/2", sealed)).err(), Some(SecurityError::BadSignature));
+
+    assert_eq!(receiver.open(&message("devices/1", b"plain".to_vec())).err(), Some(SecurityError::Malformed));
+    assert_eq!(receiver.open(&message("other", b"plain".to_vec())).err(), Some(SecurityError::Malformed));
+}
+
+#[test]
+fn replay_is_rejected() {
+    let sender = hmac_layer(None);
+    let mut receiver = hmac_layer(None);
+
+    let sealed = sender.seal("devices/1", b"hello").unwrap();
+    assert!(receiver.open(&message("devices/1", sealed.clone())).is_ok());
+    assert_eq!(receiver.open(&message("devices/1", sealed)).err(), Some(SecurityError::Replayed));
+    match MqttError::from(SecurityError::Replayed) {
+        MqttError::Security(SecurityError::Replayed) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn stale_message_is_rejected() {
+    let sender = hmac_layer(None);
+    let mut receiver = hmac_layer(None);
+    receiver.max_skew_ms = 0;
+
+    let sealed = sender.seal("devices/1", b"hello").unwrap();
+    thread::sleep(Duration::from_millis(5));
+    match receiver.open(&message("devices/1", sealed)) {
+        Err(SecurityError::Stale(_)) => {},
+        other => panic!("unexpected {:?}", other.map(|v| v.verification)),
+    }
+}
+
+#[test]
+fn missing_key() {
+    let sender = hmac_layer(None);
+    assert_eq!(sender.seal("other/1", b"hello").err(), Some(SecurityError::NoKey("other/1".to_string())));
+}
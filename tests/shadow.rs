@@ -0,0 +1,111 @@
+#![cfg(feature = "serde")]
+
+extern crate mqtt;
+extern crate serde_json;
+
+use mqtt::async::{ShadowDocument, ShadowEvent, merge_patch};
+use serde_json::Value;
+
+
+fn json(text: &str) -> Value {
+    serde_json::from_str(text).unwrap()
+}
+
+#[test]
+fn merge_patch_rfc7386() {
+    let mut target = json(r#"{"a": "b", "c": {"d": "e", "f": "g"}}"#);
+    merge_patch(&mut target, &json(r#"{"a": "z", "c": {"f": null}}"#));
+    assert_eq!(target, json(r#"{"a": "z", "c": {"d": "e"}}"#));
+    merge_patch(&mut target, &json(r#"["x"]"#));
+    assert_eq!(target, json(r#"["x"]"#));
+}
+
+#[test]
+fn desired_update_gives_delta() {
+    let mut device = ShadowDocument::new();
+    device.report(&json(r#"{"led": "off", "fan": {"speed": 1, "mode": "auto"}}"#));
+
+    let event = device.apply_desired(br#"{"version": 1, "state": {"led": "on", "fan": {"speed": 1, "mode": "eco"}}}"#);
+    assert_eq!(event, Some(ShadowEvent::Delta(json(r#"{"led": "on", "fan": {"mode": "eco"}}"#))));
+    assert_eq!(device.version, 1);
+
+    let report = device.report(&json(r#"{"led": "on", "fan": {"mode": "eco"}}"#));
+    assert_eq!(report, json(r#"{"version": 1, "state": {"led": "on", "fan": {"speed": 1, "mode": "eco"}}}"#));
+    assert_eq!(device.delta(), json("{}"));
+
+    // already reported values give no delta
+    assert_eq!(device.apply_desired(br#"{"version": 2, "state": {"led": "on"}}"#), None);
+}
+
+#[test]
+fn stale_and_invalid_updates() {
+    let mut device = ShadowDocument::new();
+    assert!(device.apply_desired(br#"{"version": 1, "state": {"a": 1}}"#).is_some());
+    assert_eq!(device.apply_desired(br#"{"version": 1, "state": {"a": 2}}"#), Some(ShadowEvent::Conflict { current: 1, received: 1 }));
+    assert_eq!(device.desired, json(r#"{"a": 1}"#));
+
+    match device.apply_desired(b"not json") {
+        Some(ShadowEvent::Invalid(_)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    match device.apply_desired(br#"{"state": {}}"#) {
+        Some(ShadowEvent::Invalid(_)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn controller_and_device_agree() {
+    let mut controller = ShadowDocument::new();
+    let mut device = ShadowDocument::new();
+    for patch in [r#"{"target": 20}"#, r#"{"target": 22}"#].iter() {
+        let update = controller.desire(&json(patch));
+        let event = device.apply_desired(&serde_json::to_vec(&update).unwrap());
+        assert_eq!(event, Some(ShadowEvent::Delta(json(patch))));
+    }
+    assert_eq!(device.desired, controller.desired);
+    assert_eq!(device.version, 2);
+}
+
+#[test]
+fn gap_needs_resync() {
+    let mut controller = ShadowDocument::new();
+    let mut device = ShadowDocument::new();
+    controller.desire(&json(r#"{"target": 20, "mode": "auto"}"#));
+    let first = controller.full_desired();
+    device.resync(&serde_json::to_vec(&first).unwrap());
+
+    // update 2 is missed, 3 only patches mode
+    controller.desire(&json(r#"{"target": 22}"#));
+    let third = controller.desire(&json(r#"{"mode": "eco"}"#));
+    assert_eq!(device.apply_desired(&serde_json::to_vec(&third).unwrap()), Some(ShadowEvent::Gap { current: 1, received: 3 }));
+    assert_eq!(device.version, 1);
+    assert_eq!(device.desired, json(r#"{"target": 20, "mode": "auto"}"#));
+
+    let full = controller.full_desired();
+    assert_eq!(device.resync(&serde_json::to_vec(&full).unwrap()), Some(ShadowEvent::Delta(json(r#"{"target": 22, "mode": "eco"}"#))));
+    assert_eq!(device.desired, controller.desired);
+    assert_eq!(device.version, 3);
+
+    // older full state is ignored
+    assert_eq!(device.resync(&serde_json::to_vec(&first).unwrap()), Some(ShadowEvent::Conflict { current: 3, received: 1 }));
+}
+
+#[test]
+fn report_after_restart_keeps_reported_state() {
+    let mut before = ShadowDocument::new();
+    before.report(&json(r#"{"target": 20, "firmware": "1.2"}"#));
+    let retained = serde_json::to_vec(&before.report(&json(r#"{"mode": "auto"}"#))).unwrap();
+
+    let mut after = ShadowDocument::new();
+    assert_eq!(after.restore_reported(&retained), None);
+    let message = after.report(&json(r#"{"target": 22}"#));
+    assert_eq!(message["state"], json(r#"{"target": 22, "firmware": "1.2", "mode": "auto"}"#));
+    assert_eq!(after.version, 0);
+
+    match after.restore_reported(b"not json") {
+        Some(ShadowEvent::Invalid(_)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(after.reported["firmware"], json(r#""1.2""#));
+}
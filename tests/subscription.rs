@@ -0,0 +1,47 @@
+extern crate mqtt;
+
+use std::thread;
+use std::time::{Duration, Instant};
+use mqtt::async::{LocalBroker, Message, Qos, TopicFilter, TopicName};
+
+
+fn publish(broker: &mut LocalBroker, topic: &str, payload: &str, retained: bool) {
+    let msg = Message::builder(TopicName::new(topic).unwrap()).payload(payload).retained(retained).build();
+    broker.publish(&msg).unwrap();
+}
+
+#[test]
+fn retained_stops_at_first_live_message() {
+    let mut broker = LocalBroker::new();
+    let subscription = broker.subscribe(&TopicFilter::new("config/#").unwrap(), Qos::AtLeastOnce).unwrap();
+    publish(&mut broker, "config/a", "1", true);
+    publish(&mut broker, "config/b", "2", true);
+    publish(&mut broker, "config/a", "3", true);
+    publish(&mut broker, "config/c", "live", false);
+    publish(&mut broker, "config/d", "4", true);
+
+    let retained = subscription.retained(1000);
+    assert_eq!(retained.keys().collect::<Vec<_>>(), vec!["config/a", "config/b"]);
+    assert_eq!(&retained["config/a"].payload[..], b"3");
+}
+
+#[test]
+fn retained_ends_after_quiet_period() {
+    let mut broker = LocalBroker::new();
+    let subscription = broker.subscribe(&TopicFilter::new("config/#").unwrap(), Qos::AtLeastOnce).unwrap();
+    publish(&mut broker, "config/a", "1", true);
+
+    let mut late = broker.clone();
+    let publisher = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        publish(&mut late, "config/b", "2", true);
+        thread::sleep(Duration::from_millis(300));
+        publish(&mut late, "config/c", "3", true);
+    });
+
+    let start = Instant::now();
+    let retained = subscription.retained(100);
+    assert!(start.elapsed() < Duration::from_millis(300));
+    assert_eq!(retained.keys().collect::<Vec<_>>(), vec!["config/a", "config/b"]);
+    publisher.join().unwrap();
+}
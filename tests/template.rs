@@ -0,0 +1,59 @@
+extern crate mqtt;
+
+use std::collections::HashMap;
+use mqtt::async::{TopicTemplate, FromTopicParams, TemplateError, TopicError};
+
+
+struct Telemetry {
+    site : String,
+    id   : u32,
+}
+
+impl FromTopicParams for Telemetry {
+    fn from_topic_params(params: &HashMap<String, String>) -> Result<Self, TemplateError> {
+        let id = try!(params["id"].parse().map_err(|_| TemplateError::Param("id".to_string())));
+        Ok(Telemetry {
+            site : params["site"].clone(),
+            id   : id,
+        })
+    }
+}
+
+#[test]
+fn render_filter_extract() {
+    let template = TopicTemplate::new("site/{site}/device/{id}/telemetry").unwrap();
+    assert_eq!(template.params(), vec!["site", "id"]);
+
+    let topic = template.render(&[("id", "7"), ("site", "tallinn")]).unwrap();
+    assert_eq!(topic.as_str(), "site/tallinn/device/7/telemetry");
+    assert_eq!(template.filter().as_str(), "site/+/device/+/telemetry");
+
+    let params = template.extract("site/tartu/device/12/telemetry").unwrap();
+    assert_eq!(params["site"], "tartu");
+    assert_eq!(params["id"], "12");
+    assert!(template.extract("site/tartu/device/12/status").is_none());
+    assert!(template.extract("site/tartu/device/12").is_none());
+
+    let telemetry: Telemetry = template.extract_into("site/tartu/device/12/telemetry").unwrap();
+    assert_eq!(telemetry.site, "tartu");
+    assert_eq!(telemetry.id, 12);
+    assert!(template.extract_into::<Telemetry>("site/tartu/device/x/telemetry").is_err());
+    assert!(template.extract_into::<Telemetry>("other").is_err());
+}
+
+#[test]
+fn render_errors() {
+    let template = TopicTemplate::new("a/{b}").unwrap();
+    assert_eq!(template.render(&[]), Err(TemplateError::MissingParam("b".to_string())));
+    assert_eq!(template.render(&[("b", "x/y")]), Err(TemplateError::InvalidValue("b".to_string(), "x/y".to_string())));
+    assert_eq!(template.render(&[("b", "+")]), Err(TemplateError::InvalidValue("b".to_string(), "+".to_string())));
+}
+
+#[test]
+fn invalid_templates() {
+    assert!(TopicTemplate::new("a/{b}x").is_err());
+    assert!(TopicTemplate::new("a/{}").is_err());
+    assert!(TopicTemplate::new("a/{b}/{b}").is_err());
+    assert!(TopicTemplate::new("a/+/{b}").is_err());
+    assert_eq!(TopicTemplate::new("").err(), Some(TemplateError::Topic(TopicError::Empty)));
+}
@@ -0,0 +1,317 @@
+extern crate mqtt;
+
+use mqtt::async::{TopicName, TopicFilter, TopicError, TopicTree, topic_matches, is_valid_topic, is_valid_filter};
+
+
+#[test]
+fn valid_topics() {
+    assert!(is_valid_topic("a"));
+    assert!(is_valid_topic("a/b/c"));
+    assert!(is_valid_topic("/"));
+    assert!(is_valid_topic("/finance"));
+    assert!(is_valid_topic("finance/"));
+    assert!(is_valid_topic("a//b"));
+    assert!(is_valid_topic(" "));
+    assert!(is_valid_topic("$SYS/broker/uptime"));
+
+    assert!(!is_valid_topic(""));
+    assert!(!is_valid_topic("a/+"));
+    assert!(!is_valid_topic("a/#"));
+    assert!(!is_valid_topic("a+b"));
+    assert!(!is_valid_topic("a\0b"));
+}
+
+#[test]
+fn valid_filters() {
+    assert!(is_valid_filter("#"));
+    assert!(is_valid_filter("+"));
+    assert!(is_valid_filter("/"));
+    assert!(is_valid_filter("+/+"));
+    assert!(is_valid_filter("/+"));
+    assert!(is_valid_filter("sport/#"));
+    assert!(is_valid_filter("sport/tennis/#"));
+    assert!(is_valid_filter("sport/+/player1"));
+    assert!(is_valid_filter("+/tennis/#"));
+    assert!(is_valid_filter("$SYS/#"));
+
+    assert!(!is_valid_filter(""));
+    assert!(!is_valid_filter("sport/tennis#"));
+    assert!(!is_valid_filter("sport/tennis/#/ranking"));
+    assert!(!is_valid_filter("#/a"));
+    assert!(!is_valid_filter("sport+"));
+    assert!(!is_valid_filter("sport/+tennis"));
+    assert!(!is_valid_filter("++"));
+    assert!(!is_valid_filter("##"));
+    assert!(!is_valid_filter("a\0b"));
+}
+
+#[test]
+fn exact_match() {
+    assert!(topic_matches("a", "a"));
+    assert!(topic_matches("a/b/c", "a/b/c"));
+    assert!(topic_matches("/", "/"));
+    assert!(topic_matches("/a", "/a"));
+
+    assert!(!topic_matches("a", "b"));
+    assert!(!topic_matches("a/b", "a"));
+    assert!(!topic_matches("a", "a/b"));
+    assert!(!topic_matches("a", "A"));
+    assert!(!topic_matches("a", "a/"));
+    assert!(!topic_matches("/a", "a"));
+    assert!(!topic_matches("a//b", "a/b"));
+}
+
+#[test]
+fn multi_level_wildcard() {
+    // examples from MQTT 3.1.1 section 4.7.1.2
+    assert!(topic_matches("sport/tennis/player1/#", "sport/tennis/player1"));
+    assert!(topic_matches("sport/tennis/player1/#", "sport/tennis/player1/ranking"));
+    assert!(topic_matches("sport/tennis/player1/#", "sport/tennis/player1/score/wimbledon"));
+    assert!(topic_matches("sport/#", "sport"));
+    assert!(topic_matches("#", "sport"));
+    assert!(topic_matches("#", "sport/tennis"));
+    assert!(topic_matches("#", "/"));
+    assert!(topic_matches("#", "/finance"));
+    assert!(topic_matches("a/#", "a/"));
+    assert!(topic_matches("a/#", "a//b"));
+
+    assert!(!topic_matches("sport/tennis/player1/#", "sport/tennis/player2"));
+    assert!(!topic_matches("sport/tennis/player1/#", "sport/tennis"));
+    assert!(!topic_matches("a/#", "ab"));
+    assert!(!topic_matches("a/#", "b/a"));
+}
+
+#[test]
+fn single_level_wildcard() {
+    // examples from MQTT 3.1.1 section 4.7.1.3
+    assert!(topic_matches("sport/tennis/+", "sport/tennis/player1"));
+    assert!(topic_matches("sport/tennis/+", "sport/tennis/player2"));
+    assert!(!topic_matches("sport/tennis/+", "sport/tennis/player1/ranking"));
+    assert!(topic_matches("sport/+", "sport/"));
+    assert!(!topic_matches("sport/+", "sport"));
+    assert!(topic_matches("+/+", "/finance"));
+    assert!(topic_matches("/+", "/finance"));
+    assert!(!topic_matches("+", "/finance"));
+    assert!(topic_matches("+", "finance"));
+    assert!(topic_matches("+/tennis/#", "sport/tennis/player1"));
+    assert!(topic_matches("sport/+/player1", "sport/tennis/player1"));
+    assert!(!topic_matches("sport/+/player1", "sport/player1"));
+    assert!(topic_matches("+/+/+", "a/b/c"));
+    assert!(topic_matches("a/+/#", "a/b"));
+    assert!(!topic_matches("a/+/#", "a"));
+}
+
+#[test]
+fn dollar_topics() {
+    // MQTT 3.1.1 section 4.7.2
+    assert!(!topic_matches("#", "$SYS"));
+    assert!(!topic_matches("#", "$SYS/broker/uptime"));
+    assert!(!topic_matches("+/monitor/Clients", "$SYS/monitor/Clients"));
+    assert!(!topic_matches("+", "$SYS"));
+    assert!(topic_matches("$SYS/#", "$SYS/monitor/Clients"));
+    assert!(topic_matches("$SYS/monitor/+", "$SYS/monitor/Clients"));
+    assert!(topic_matches("$SYS/#", "$SYS"));
+    assert!(topic_matches("$SYS", "$SYS"));
+    assert!(topic_matches("$share/#", "$share/a"));
+    // '$' is an ordinary character below the first level
+    assert!(topic_matches("a/+", "a/$b"));
+    assert!(topic_matches("a/#", "a/$b/c"));
+}
+
+#[test]
+fn invalid_never_matches() {
+    assert!(!topic_matches("", ""));
+    assert!(!topic_matches("a", ""));
+    assert!(!topic_matches("", "a"));
+    assert!(!topic_matches("a/#/b", "a/x/b"));
+    assert!(!topic_matches("a#", "a#"));
+    assert!(!topic_matches("a/+", "a/+"));
+    assert!(!topic_matches("#", "a/#"));
+}
+
+#[test]
+fn tree_insert_and_match() {
+    let mut tree = TopicTree::new();
+    assert!(tree.is_empty());
+    assert!(tree.insert("sport/tennis/player1", 1));
+    assert!(tree.insert("sport/tennis/+", 2));
+    assert!(tree.insert("sport/#", 3));
+    assert!(tree.insert("#", 4));
+    assert!(tree.insert("+/+/+", 5));
+    assert!(tree.insert("other", 6));
+    assert_eq!(tree.len(), 6);
+
+    let mut found: Vec<i32> = tree.matches("sport/tennis/player1").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![1, 2, 3, 4, 5]);
+
+    let mut found: Vec<i32> = tree.matches("sport").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![3, 4]);
+
+    let mut found: Vec<i32> = tree.matches("other").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![4, 6]);
+
+    assert!(tree.matches("").is_empty());
+    assert!(tree.matches("sport/+").is_empty());
+}
+
+#[test]
+fn tree_contains_exact_filter() {
+    let mut tree = TopicTree::new();
+    tree.insert("a/+", 1);
+    assert!(tree.contains("a/+"));
+    assert!(!tree.contains("a/b"));
+    assert!(!tree.contains("a"));
+    tree.remove("a/+");
+    assert!(!tree.contains("a/+"));
+}
+
+#[test]
+fn tree_rejects_invalid_filters() {
+    let mut tree = TopicTree::new();
+    assert!(!tree.insert("", 1));
+    assert!(!tree.insert("a/#/b", 1));
+    assert!(!tree.insert("a+", 1));
+    assert!(tree.is_empty());
+}
+
+#[test]
+fn tree_multiple_values_per_filter() {
+    let mut tree = TopicTree::new();
+    tree.insert("a/b", "x");
+    tree.insert("a/b", "y");
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.matches("a/b").len(), 2);
+}
+
+#[test]
+fn tree_parent_level_of_hash() {
+    let mut tree = TopicTree::new();
+    tree.insert("a/#", 1);
+    tree.insert("a/+/#", 2);
+    assert_eq!(tree.matches("a").into_iter().cloned().collect::<Vec<_>>(), vec![1]);
+    let mut found: Vec<i32> = tree.matches("a/b").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![1, 2]);
+}
+
+#[test]
+fn tree_dollar_topics() {
+    let mut tree = TopicTree::new();
+    tree.insert("#", 1);
+    tree.insert("+/broker/uptime", 2);
+    tree.insert("$SYS/#", 3);
+    tree.insert("$SYS/broker/+", 4);
+    let mut found: Vec<i32> = tree.matches("$SYS/broker/uptime").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![3, 4]);
+    let mut found: Vec<i32> = tree.matches("x/broker/uptime").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![1, 2]);
+}
+
+#[test]
+fn tree_empty_levels() {
+    let mut tree = TopicTree::new();
+    tree.insert("/", 1);
+    tree.insert("/+", 2);
+    tree.insert("+/+", 3);
+    tree.insert("+", 4);
+    let mut found: Vec<i32> = tree.matches("/").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![1, 2, 3]);
+    let mut found: Vec<i32> = tree.matches("finance").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![4]);
+}
+
+#[test]
+fn tree_remove() {
+    let mut tree = TopicTree::new();
+    tree.insert("a/b", 1);
+    tree.insert("a/b", 2);
+    tree.insert("a/#", 3);
+    assert_eq!(tree.remove("a/b"), vec![1, 2]);
+    assert_eq!(tree.len(), 1);
+    assert!(tree.remove("a/b").is_empty());
+    assert!(tree.remove("x/y").is_empty());
+    assert_eq!(tree.matches("a/b").into_iter().cloned().collect::<Vec<_>>(), vec![3]);
+    assert_eq!(tree.remove("a/#"), vec![3]);
+    assert!(tree.is_empty());
+    assert!(tree.matches("a/b").is_empty());
+}
+
+#[test]
+fn tree_retain() {
+    let mut tree = TopicTree::new();
+    tree.insert("a/b", 1);
+    tree.insert("a/+", 2);
+    tree.insert("c", 3);
+    tree.retain(|filter, value| filter != "a/+" && *value != 3);
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree.matches("a/b").into_iter().cloned().collect::<Vec<_>>(), vec![1]);
+    assert!(tree.matches("c").is_empty());
+}
+
+#[test]
+fn tree_agrees_with_matcher() {
+    let filters = ["#", "+", "a", "a/#", "a/+", "a/b", "+/b", "+/+", "a/+/c", "+/#", "/#", "$SYS/#", "a//c", "/"];
+    let topics  = ["a", "b", "a/b", "a/c", "b/b", "a/b/c", "a//c", "/", "/a", "$SYS", "$SYS/x", "a/"];
+
+    let mut tree = TopicTree::new();
+    for filter in filters.iter() {
+        tree.insert(filter, *filter);
+    }
+    for topic in topics.iter() {
+        let mut found: Vec<&str> = tree.matches(topic).into_iter().cloned().collect();
+        found.sort();
+        let mut expected: Vec<&str> = filters.iter().cloned().filter(|f| topic_matches(f, topic)).collect();
+        expected.sort();
+        assert_eq!(found, expected, "topic {}", topic);
+    }
+}
+
+#[test]
+fn tree_many_filters() {
+    let mut tree = TopicTree::new();
+    for i in 0..5000 {
+        tree.insert(&format!("sensors/{}/temp", i), i);
+    }
+    tree.insert("sensors/+/temp", -1);
+    assert_eq!(tree.len(), 5001);
+    let mut found: Vec<i32> = tree.matches("sensors/1234/temp").into_iter().cloned().collect();
+    found.sort();
+    assert_eq!(found, vec![-1, 1234]);
+}
+
+#[test]
+fn topic_name_validation() {
+    assert!(TopicName::new("a/b").is_ok());
+    assert_eq!(TopicName::new(""), Err(TopicError::Empty));
+    assert_eq!(TopicName::new("a/+"), Err(TopicError::WildcardInName));
+    assert_eq!(TopicName::new("a/#"), Err(TopicError::WildcardInName));
+    assert_eq!(TopicName::new("a\0"), Err(TopicError::NullCharacter));
+    assert_eq!(TopicName::new(vec!["a"; 65536].concat()), Err(TopicError::TooLong(65536)));
+    assert!(TopicName::new(vec!["a"; 65535].concat()).is_ok());
+    assert_eq!("x/y".parse::<TopicName>().unwrap().as_str(), "x/y");
+}
+
+#[test]
+fn topic_filter_validation() {
+    assert!(TopicFilter::new("a/+/#").is_ok());
+    assert_eq!(TopicFilter::new(""), Err(TopicError::Empty));
+    assert_eq!(TopicFilter::new("a/#/b"), Err(TopicError::InvalidWildcard));
+    assert_eq!(TopicFilter::new("a+"), Err(TopicError::InvalidWildcard));
+    assert_eq!(TopicFilter::new("a\0"), Err(TopicError::NullCharacter));
+
+    let filter = TopicFilter::new("sensors/+/temp").unwrap();
+    assert!(filter.has_wildcards());
+    assert!(filter.matches("sensors/1/temp"));
+    assert!(!filter.matches("sensors/1/humidity"));
+
+    let exact = TopicFilter::from(TopicName::new("sensors/1/temp").unwrap());
+    assert!(!exact.has_wildcards());
+    assert!(exact.matches("sensors/1/temp"));
+}